@@ -0,0 +1,177 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use ndarray::{Array, Array2, Array3, Axis, Dimension, ShapeBuilder};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::initializer::Initializer;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+
+// Matches the dimensionality of the bundled word embeddings
+const DIMENSIONALITY: usize = 50;
+const SEQUENCE_LENGTHS: [usize; 3] = [12, 64, 256];
+const MIN_RUNS: u32 = 3;
+const MIN_DURATION: Duration = Duration::from_millis(500);
+
+fn random<Sh: ShapeBuilder>(shape: Sh, rng: &mut StdRng) -> Array<f32, Sh::Dim> where Sh::Dim: Dimension {
+    Array::from_shape_simple_fn(shape, || rng.gen_range(-1.0..1.0))
+}
+
+/// Returns the mean time taken by `f`, running it for at least `MIN_DURATION`
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs < MIN_RUNS || start.elapsed() < MIN_DURATION {
+        black_box(f());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+/// The original self-attention, which compares every pair of words in nested
+/// loops, kept as a reference for the speed-up of the matrix implementation.
+/// Gradients are accumulated rather than applied so both do the same work.
+struct LoopAttention {
+    key: Array2<f32>,
+    query: Array2<f32>,
+    value: Array2<f32>,
+    weights: Array2<f32>,
+    value_vecs: Array2<f32>,
+    vec_key_matrix: Array3<f32>,
+    vec_query_matrix: Array3<f32>,
+}
+
+impl LoopAttention {
+    fn forward(&mut self, input: &Array2<f32>) -> Array2<f32> {
+        let (n, d) = input.dim();
+        self.weights = Array2::zeros((n, n));
+        self.vec_key_matrix = Array3::zeros((n, n, d));
+        self.vec_query_matrix = Array3::zeros((n, n, d));
+
+        for i in 0..n {
+            for j in 0..n {
+                let vec_query = input.index_axis(Axis(0), i).dot(&self.query);
+                let vec_key = input.index_axis(Axis(0), j).dot(&self.key);
+                for k in 0..d {
+                    self.vec_key_matrix[[i, j, k]] = vec_key[k];
+                    self.vec_query_matrix[[i, j, k]] = vec_query[k];
+                }
+                self.weights[[i, j]] = vec_query.dot(&vec_key);
+            }
+        }
+
+        for mut x in self.weights.axis_iter_mut(Axis(0)) {
+            let highest = x.fold(f32::NEG_INFINITY, |m, &e| m.max(e));
+            x.mapv_inplace(|e| (e - highest).exp());
+            let norm = x.sum();
+            x.mapv_inplace(|e| e / norm);
+        }
+
+        self.value_vecs = Array2::zeros((n, d));
+        for i in 0..n {
+            self.value_vecs.row_mut(i).assign(&input.index_axis(Axis(0), i).dot(&self.value));
+        }
+
+        let mut output = Array2::zeros((n, d));
+        for i in 0..n {
+            for j in 0..d {
+                for k in 0..n {
+                    output[[i, j]] += self.value_vecs[[k, j]] * self.weights[[i, k]];
+                }
+            }
+        }
+        output
+    }
+
+    fn backward(&mut self, input: &Array2<f32>, error: &Array2<f32>) -> Array2<f32> {
+        let (n, d) = input.dim();
+        let mut value_error = Array2::<f32>::zeros((n, d));
+        let mut weight_rate = Array2::<f32>::zeros((n, n));
+        let mut value_grad = Array2::<f32>::zeros((d, d));
+
+        for j in 0..d {
+            for k in 0..n {
+                for i in 0..n {
+                    value_error[[k, j]] += error[[i, j]] * self.weights[[i, k]];
+                    weight_rate[[k, i]] += error[[k, j]] * self.value_vecs[[i, j]];
+                }
+                for l in 0..d {
+                    value_grad[[l, j]] += value_error[[k, j]] * input[[k, l]];
+                }
+            }
+        }
+
+        let mut prev_error = Array2::<f32>::zeros((n, d));
+        let mut unnormalised_error = Array2::<f32>::zeros((n, n));
+        let mut key_grad = Array2::<f32>::zeros((d, d));
+        let mut query_grad = Array2::<f32>::zeros((d, d));
+
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..n {
+                    let output_rate = if j == k {
+                        self.weights[[i, j]] * (1.0 - self.weights[[i, j]])
+                    } else {
+                        -self.weights[[i, j]] * self.weights[[i, k]]
+                    };
+                    unnormalised_error[[i, j]] += output_rate * weight_rate[[i, k]];
+                }
+                for k in 0..d {
+                    let key_rate = self.vec_query_matrix[[i, j, k]] * unnormalised_error[[i, j]];
+                    let query_rate = self.vec_key_matrix[[i, j, k]] * unnormalised_error[[i, j]];
+                    for l in 0..d {
+                        key_grad[[l, k]] += key_rate * input[[i, l]];
+                        query_grad[[l, k]] += query_rate * input[[i, l]];
+                        prev_error[[i, l]] += key_rate * self.key[[l, k]];
+                        prev_error[[i, l]] += query_rate * self.query[[l, k]];
+                    }
+                }
+            }
+        }
+
+        black_box((value_grad, key_grad, query_grad));
+        prev_error
+    }
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    println!("{:>8} {:>12} {:>12} {:>9}", "length", "loops", "matrices", "speed-up");
+
+    for length in SEQUENCE_LENGTHS {
+        let input = random((length, DIMENSIONALITY), &mut rng);
+        let error = random((length, DIMENSIONALITY), &mut rng);
+
+        let mut loops = LoopAttention {
+            key: random((DIMENSIONALITY, DIMENSIONALITY), &mut rng),
+            query: random((DIMENSIONALITY, DIMENSIONALITY), &mut rng),
+            value: random((DIMENSIONALITY, DIMENSIONALITY), &mut rng),
+            weights: Array2::zeros((0, 0)),
+            value_vecs: Array2::zeros((0, 0)),
+            vec_key_matrix: Array3::zeros((0, 0, 0)),
+            vec_query_matrix: Array3::zeros((0, 0, 0)),
+        };
+        let loop_time = time(|| {
+            loops.forward(&input);
+            loops.backward(&input, &error)
+        });
+
+        // A batch of one example, so both implementations see the same data
+        let mut attention = SelfAttention::new(length, DIMENSIONALITY, DIMENSIONALITY, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+        let batch_input = input.clone().insert_axis(Axis(0));
+        let batch_error = error.clone().insert_axis(Axis(0));
+        let matrix_time = time(|| {
+            attention.forward_propagate(batch_input.clone());
+            attention.back_propagate(batch_error.clone())
+        });
+
+        println!(
+            "{:>8} {:>10.3}ms {:>10.3}ms {:>8.1}x",
+            length,
+            loop_time.as_secs_f64() * 1e3,
+            matrix_time.as_secs_f64() * 1e3,
+            loop_time.as_secs_f64() / matrix_time.as_secs_f64(),
+        );
+    }
+}
\ No newline at end of file
@@ -1,82 +1,86 @@
-use ndarray::{Axis,Array2};
+use ndarray::{Array3, ArrayD, Ix3, IxDyn};
+use crate::autograd::{Tape, Var};
 use crate::block::Block;
+use crate::float::Float;
+
+// Keeps the normalisation finite for word vectors with no variance, such as padding
+const EPSILON: f64 = 1e-5;
 
 // Defines an add and norm struct
-pub struct AddAndNorm {
-    original_input: Array2::<f32>,
-    modified_input: Array2::<f32>,
+#[derive(Default)]
+pub struct AddAndNorm<F: Float = f32> {
+    tape: Tape<F>,
+    original_var: Var,
+    modified_var: Var,
+    output_var: Var,
 }
 
-impl AddAndNorm {
-    /// Create a new add and norm block with the given parameters
-    pub fn new(rows: usize, cols: usize) -> AddAndNorm {
+impl<F: Float> AddAndNorm<F> {
+    /// Create a new add and norm block, which works on word vectors of any size
+    pub fn new() -> AddAndNorm<F> {
 
-        let block: AddAndNorm = AddAndNorm {
-            original_input: Array2::<f32>::zeros((rows, cols)),
-            modified_input: Array2::<f32>::zeros((rows, cols)),
+        let block: AddAndNorm<F> = AddAndNorm {
+            tape: Tape::new(),
+            original_var: Var::default(),
+            modified_var: Var::default(),
+            output_var: Var::default(),
         };
 
         block
     }
 }
 
-impl Block for AddAndNorm {
-    type Input = (Array2<f32>, Array2<f32>);
-    type Output = Array2<f32>;
+/// Records the sum of the two inputs normalised over each word vector on a tape
+fn record<F: Float>(tape: &mut Tape<F>, original: Var, modified: Var) -> Var {
+    // Perform element-wise addition of original and modified inputs
+    let sum = tape.add(original, modified);
 
-    // Implementation of forward propagation
-    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
-        // Store the original and modified inputs
-        self.original_input = value.0;
-        self.modified_input = value.1;
+    // Calculate the mean and standard deviation of each word vector (last axis)
+    let mean = tape.mean(sum);
+    let centred = tape.sub(sum, mean);
+    let squared = tape.mul(centred, centred);
+    let variance = tape.mean(squared);
+    let epsilon = tape.leaf(ArrayD::from_elem(IxDyn(&[1]), F::cast(EPSILON)));
+    let variance = tape.add(variance, epsilon);
+    let stdev = tape.sqrt(variance);
 
-        // Perform element-wise addition of original and modified inputs
-        let mut output = &self.original_input + &self.modified_input;
+    // Normalize each element in the row using mean and standard deviation
+    tape.div(centred, stdev)
+}
 
-        // Iterate over each row (axis 0) of the output matrix
-        for mut x in output.axis_iter_mut(Axis(0)) {
-            // Calculate the sum of squares for each row
-            let sum_sq = x.mapv(|x| x * x).sum();
-            let n = x.len(); // Get the length of the row
-            let mean = x.sum() / n as f32; // Calculate the mean of the row
-            let mean_sq = sum_sq / n as f32; // Calculate the mean of squares
-            let stdev = (mean_sq - mean.powf(2.0)).powf(0.5); // Calculate the standard deviation
+impl<F: Float> Block<F> for AddAndNorm<F> {
+    type Input = (Array3<F>, Array3<F>);
+    type Output = Array3<F>;
 
-            // Normalize each element in the row using mean and standard deviation
-            x.mapv_inplace(|y| (y - mean) / stdev);
-        }
+    // Implementation of forward propagation
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        // Record the normalisation on a fresh tape, keeping it for back propagation
+        let mut tape = Tape::new();
+        self.original_var = tape.leaf(value.0.into_dyn());
+        self.modified_var = tape.leaf(value.1.into_dyn());
+        self.output_var = record(&mut tape, self.original_var, self.modified_var);
+        self.tape = tape;
 
         // Return the normalized output
-        output
+        self.tape.value(self.output_var).clone().into_dimensionality::<Ix3>().unwrap()
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        let mut tape = Tape::new();
+        let original = tape.leaf(value.0.into_dyn());
+        let modified = tape.leaf(value.1.into_dyn());
+        let output = record(&mut tape, original, modified);
+        tape.value(output).clone().into_dimensionality::<Ix3>().unwrap()
     }
 
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
         // Each input element in the word vector affects the output in multiple
-        // ways as it's used in the stdev and mean calcs, so each word vector has
-        // a Jacobean for its dC / dx
+        // ways as it's used in the stdev and mean calcs, which the tape accounts for
+        let grads = self.tape.backward(self.output_var, error.into_dyn());
 
-        let shape = (error.shape()[0], error.shape()[1]);
-        let mut prev_error: Self::Input = (Array2::<f32>::zeros(shape), Array2::<f32>::zeros(shape));
+        let original_error = grads.get(self.original_var, &self.tape).into_dimensionality::<Ix3>().unwrap();
+        let modified_error = grads.get(self.modified_var, &self.tape).into_dimensionality::<Ix3>().unwrap();
 
-        let input = &self.original_input + &self.modified_input;
-        for (count, x) in input.axis_iter(Axis(0)).enumerate() {
-            let n = x.len() as f32;
-            let i = Array2::<f32>::eye(n as usize);
-            let mean = x.mean().unwrap();
-            let stdev = x.std(0.0);
-            // Make a matrix from (xi-μ) * (xj-μ) for use in the jacobean
-            let x_matrix = Array2::from_shape_fn((n as usize, n as usize), |(i, j)| (&x - mean)[i] * (&x - mean)[j]);
-            let jacobean = ((i * n) - 1.0) / (n * stdev) - (x_matrix / (n * stdev.powi(3)));
-            // Calculate all the dC / dx for each input x. There will be n rates of change per input element.
-            let p = Array2::from_shape_fn((n as usize, n as usize), |(i, j)| error[[count, i]] * jacobean[[i, j]]);
-            // Sum each rate of change for each input to get the final dC / dx.
-            let row_error = p.sum_axis(Axis(0));
-            for j in 0..error.shape()[1] {
-                prev_error.0[[count, j]] = row_error[j];
-                prev_error.1[[count, j]] = row_error[j];
-            }
-        }
-
-        prev_error
+        (original_error, modified_error)
     }
-}
\ No newline at end of file
+}
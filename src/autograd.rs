@@ -0,0 +1,521 @@
+use std::ops::Range;
+use ndarray::{concatenate, s, Array2, Array3, ArrayD, ArrayView2, ArrayViewMut1, Axis, Ix2, Ix3, IxDyn, Slice, Zip};
+use crate::float::Float;
+use crate::window::Pattern;
+
+/// A handle to a value recorded on a tape
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Var(usize);
+
+// Defines the operations a tape can record
+enum Op<F> {
+    Leaf,
+    Add(Var, Var),
+    Sub(Var, Var),
+    Mul(Var, Var),
+    Div(Var, Var),
+    Scale(Var, F),
+    MatMul(Var, Var),
+    Transpose(Var),
+    Reshape(Var),
+    Slice(Var, usize),
+    Concatenate(Vec<Var>),
+    Relu(Var),
+    Sigmoid(Var),
+    Sqrt(Var),
+    Softmax(Var),
+    Mean(Var),
+    Attention { query: Var, key: Var, value: Var, bias: Option<Var>, weights: ArrayD<F> },
+    SparseAttention { query: Var, key: Var, value: Var, bias: Option<Var>, pattern: Pattern, weights: Array2<F> },
+}
+
+// Defines a single recorded value and the operation which produced it
+struct Node<F> {
+    value: ArrayD<F>,
+    op: Op<F>,
+}
+
+/// A tape which records operations during forward propagation so that
+/// gradients can be computed by reverse-mode differentiation
+#[derive(Default)]
+pub struct Tape<F: Float = f32> {
+    nodes: Vec<Node<F>>,
+}
+
+/// Gradients of a tape's output with respect to each recorded value
+pub struct Gradients<F: Float = f32> {
+    grads: Vec<Option<ArrayD<F>>>,
+}
+
+impl<F: Float> Gradients<F> {
+    /// Returns the gradient for the given leaf value, or zeros if it did not affect the output
+    pub fn get(&self, var: Var, tape: &Tape<F>) -> ArrayD<F> {
+        match &self.grads[var.0] {
+            Some(grad) => standard_layout(grad.clone()),
+            None => ArrayD::<F>::zeros(tape.value(var).raw_dim()),
+        }
+    }
+}
+
+/// Sums a broadcast gradient back down to the shape of the original operand
+fn unbroadcast<F: Float>(grad: ArrayD<F>, shape: &[usize]) -> ArrayD<F> {
+    let mut grad = grad;
+
+    // Sum over any leading axes which were added by broadcasting
+    while grad.ndim() > shape.len() {
+        grad = grad.sum_axis(Axis(0));
+    }
+
+    // Sum over any axes which were stretched from a length of one
+    for (axis, &len) in shape.iter().enumerate() {
+        if len == 1 && grad.shape()[axis] != 1 {
+            grad = grad.sum_axis(Axis(axis)).insert_axis(Axis(axis));
+        }
+    }
+
+    grad
+}
+
+/// Copies a value into row-major order if it is not already. Matrix products of
+/// single rows or columns can come out in column-major order, which would
+/// silently reorder elements when reshaped.
+fn standard_layout<F: Float>(a: ArrayD<F>) -> ArrayD<F> {
+    if a.is_standard_layout() {
+        a
+    } else {
+        a.as_standard_layout().into_owned()
+    }
+}
+
+/// Views a value as a 2D matrix by merging every axis except the last
+fn flatten<F: Float>(a: &ArrayD<F>) -> ArrayView2<'_, F> {
+    let cols = a.shape()[a.ndim() - 1];
+    a.view().into_shape((a.len() / cols, cols)).expect("matmul expects contiguous values")
+}
+
+/// Multiplies two values. A 2D right operand is applied to every row of the left
+/// operand, otherwise both operands are treated as batches of matrices which share
+/// their leading axes.
+fn dot<F: Float>(a: &ArrayD<F>, b: &ArrayD<F>) -> ArrayD<F> {
+    let rows = a.shape()[a.ndim() - 2];
+    let cols = b.shape()[b.ndim() - 1];
+    let mut shape = a.shape().to_vec();
+    shape[a.ndim() - 1] = cols;
+
+    if b.ndim() == 2 {
+        let b = b.view().into_dimensionality::<Ix2>().unwrap();
+        return standard_layout(flatten(a).dot(&b).into_dyn()).into_shape(IxDyn(&shape)).unwrap();
+    }
+
+    // Multiply each matrix in the batch separately
+    let inner = a.shape()[a.ndim() - 1];
+    let a = a.view().into_shape((a.len() / (rows * inner), rows, inner)).expect("matmul expects contiguous values");
+    let b = b.view().into_shape((b.len() / (inner * cols), inner, cols)).expect("matmul expects contiguous values");
+    let mut output = Array3::<F>::zeros((a.shape()[0], rows, cols));
+    for (i, mut matrix) in output.outer_iter_mut().enumerate() {
+        matrix.assign(&a.index_axis(Axis(0), i).dot(&b.index_axis(Axis(0), i)));
+    }
+    output.into_shape(IxDyn(&shape)).unwrap()
+}
+
+/// Finds the gradient of the right operand of a matrix multiplication
+fn dot_rhs_grad<F: Float>(a: &ArrayD<F>, b: &ArrayD<F>, grad: &ArrayD<F>) -> ArrayD<F> {
+    if b.ndim() == 2 {
+        // The same matrix was applied to every row, so sum the contribution of each row
+        standard_layout(flatten(a).t().dot(&flatten(grad)).into_dyn())
+    } else {
+        dot(&transpose(a), grad)
+    }
+}
+
+/// Normalises a value with softmax over its last axis
+fn softmax<F: Float>(mut value: ArrayD<F>) -> ArrayD<F> {
+    let last = Axis(value.ndim() - 1);
+    for x in value.lanes_mut(last) {
+        normalise(x);
+    }
+    value
+}
+
+/// Applies softmax to a single lane in place
+fn normalise<F: Float>(mut x: ArrayViewMut1<F>) {
+    // Subtract the highest value before exponentiating for numerical stability
+    let highest = x.fold(F::neg_infinity(), |m, &e| m.max(e));
+    if highest == F::neg_infinity() {
+        // Every element is masked out, so nothing gets any weight
+        x.fill(F::zero());
+        return;
+    }
+    x.mapv_inplace(|e| (e - highest).exp());
+    let norm = x.sum();
+    x.mapv_inplace(|e| e / norm);
+}
+
+/// Finds the gradient of the scores given to softmax from the gradient of its output
+fn softmax_grad<F: Float>(output: &ArrayD<F>, grad: ArrayD<F>) -> ArrayD<F> {
+    // dx = y * (g - sum(g * y)) over the normalised axis
+    let last = Axis(output.ndim() - 1);
+    let weighted = (&grad * output).sum_axis(last).insert_axis(last);
+    output * &(grad - weighted)
+}
+
+/// Swaps the last two axes of a value
+fn transpose<F: Float>(a: &ArrayD<F>) -> ArrayD<F> {
+    let mut t = a.view();
+    let n = t.ndim();
+    t.swap_axes(n - 2, n - 1);
+    t.as_standard_layout().into_owned()
+}
+
+impl<F: Float> Tape<F> {
+    /// Create a new, empty tape
+    pub fn new() -> Tape<F> {
+        Tape { nodes: vec![] }
+    }
+
+    /// Returns the value recorded for the given handle
+    pub fn value(&self, var: Var) -> &ArrayD<F> {
+        &self.nodes[var.0].value
+    }
+
+    /// Returns the softmax weights of an attention operation, or `None` if the
+    /// handle was recorded by any other operation. Sparse attention gives every
+    /// pair of words it didn't attend to a weight of zero.
+    pub fn attention_weights(&self, var: Var) -> Option<ArrayD<F>> {
+        match &self.nodes[var.0].op {
+            Op::Attention { weights, .. } => Some(weights.clone()),
+            Op::SparseAttention { pattern, weights, .. } => {
+                let mut dense = Array3::<F>::zeros((weights.nrows(), pattern.queries(), pattern.num_keys()));
+                for i in 0..pattern.queries() {
+                    for pair in pattern.row(i) {
+                        dense.slice_mut(s![.., i, pattern.key(pair)]).assign(&weights.column(pair));
+                    }
+                }
+                Some(dense.into_dyn())
+            }
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, value: ArrayD<F>, op: Op<F>) -> Var {
+        // Keep every value in row-major order so reshaping never reorders elements
+        let value = standard_layout(value);
+        self.nodes.push(Node { value, op });
+        Var(self.nodes.len() - 1)
+    }
+
+    /// Records an input or parameter value
+    pub fn leaf(&mut self, value: ArrayD<F>) -> Var {
+        self.push(value, Op::Leaf)
+    }
+
+    /// Element-wise addition, broadcasting both operands
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        let value = self.value(a) + self.value(b);
+        self.push(value, Op::Add(a, b))
+    }
+
+    /// Element-wise subtraction, broadcasting both operands
+    pub fn sub(&mut self, a: Var, b: Var) -> Var {
+        let value = self.value(a) - self.value(b);
+        self.push(value, Op::Sub(a, b))
+    }
+
+    /// Element-wise multiplication, broadcasting both operands
+    pub fn mul(&mut self, a: Var, b: Var) -> Var {
+        let value = self.value(a) * self.value(b);
+        self.push(value, Op::Mul(a, b))
+    }
+
+    /// Element-wise division, broadcasting both operands
+    pub fn div(&mut self, a: Var, b: Var) -> Var {
+        let value = self.value(a) / self.value(b);
+        self.push(value, Op::Div(a, b))
+    }
+
+    /// Multiplies every element by a constant
+    pub fn scale(&mut self, a: Var, factor: F) -> Var {
+        let value = self.value(a) * factor;
+        self.push(value, Op::Scale(a, factor))
+    }
+
+    /// Matrix multiplication over the last two axes
+    pub fn matmul(&mut self, a: Var, b: Var) -> Var {
+        let value = dot(self.value(a), self.value(b));
+        self.push(value, Op::MatMul(a, b))
+    }
+
+    /// Swaps the last two axes
+    pub fn transpose(&mut self, a: Var) -> Var {
+        let value = transpose(self.value(a));
+        self.push(value, Op::Transpose(a))
+    }
+
+    /// Changes the shape of a value without changing its elements
+    pub fn reshape(&mut self, a: Var, shape: &[usize]) -> Var {
+        let value = self.value(a).clone().into_shape(IxDyn(shape)).unwrap();
+        self.push(value, Op::Reshape(a))
+    }
+
+    /// Takes the given range of the last axis
+    pub fn slice(&mut self, a: Var, range: Range<usize>) -> Var {
+        let value = self.value(a);
+        let last = Axis(value.ndim() - 1);
+        let value = value.slice_axis(last, Slice::from(range.clone())).to_owned();
+        self.push(value, Op::Slice(a, range.start))
+    }
+
+    /// Joins values end to end along the last axis
+    pub fn concatenate(&mut self, parts: &[Var]) -> Var {
+        let views: Vec<_> = parts.iter().map(|&part| self.value(part).view()).collect();
+        let value = concatenate(Axis(views[0].ndim() - 1), &views).unwrap();
+        self.push(value, Op::Concatenate(parts.to_vec()))
+    }
+
+    /// Rectified linear unit
+    pub fn relu(&mut self, a: Var) -> Var {
+        let value = self.value(a).mapv(|x| if x > F::zero() { x } else { F::zero() });
+        self.push(value, Op::Relu(a))
+    }
+
+    /// Sigmoid activation
+    pub fn sigmoid(&mut self, a: Var) -> Var {
+        let value = self.value(a).mapv(|x| F::one() / (F::one() + (-x).exp()));
+        self.push(value, Op::Sigmoid(a))
+    }
+
+    /// Element-wise square root
+    pub fn sqrt(&mut self, a: Var) -> Var {
+        let value = self.value(a).mapv(F::sqrt);
+        self.push(value, Op::Sqrt(a))
+    }
+
+    /// Softmax normalisation over the last axis
+    pub fn softmax(&mut self, a: Var) -> Var {
+        let value = softmax(self.value(a).clone());
+        self.push(value, Op::Softmax(a))
+    }
+
+    /// Unscaled dot-product attention, softmax(QKᵀ + B)V, over the last two axes.
+    /// Recording it as one operation keeps only the attention weights for the
+    /// backward pass instead of every intermediate matrix. The bias B and the mask
+    /// are broadcast to the shape of QKᵀ, and where the mask is false the weight is zero.
+    pub fn attention(&mut self, query: Var, key: Var, value: Var, bias: Option<Var>, mask: Option<&ArrayD<bool>>) -> Var {
+        let mut scores = dot(self.value(query), &transpose(self.value(key)));
+        if let Some(bias) = bias {
+            scores += self.value(bias);
+        }
+        if let Some(mask) = mask {
+            let mask = mask.broadcast(scores.raw_dim()).expect("mask must broadcast to the attention scores");
+            Zip::from(&mut scores).and(&mask).for_each(|score, &keep| {
+                if !keep {
+                    *score = F::neg_infinity();
+                }
+            });
+        }
+        let weights = softmax(scores);
+        let output = dot(&weights, self.value(value));
+        self.push(output, Op::Attention { query, key, value, bias, weights })
+    }
+
+    /// Attention which only scores the pairs of words in `pattern`, for queries, keys
+    /// and values in the shape (batch, words, size). Any bias has one value per pair
+    /// and the mask must broadcast to the shape (batch, pairs). Only the weights of those
+    /// pairs are stored, so memory grows with the number of pairs rather than words².
+    pub fn sparse_attention(&mut self, query: Var, key: Var, value: Var, pattern: &Pattern, bias: Option<Var>, mask: Option<&ArrayD<bool>>) -> Var {
+        let q = self.value(query).view().into_dimensionality::<Ix3>().unwrap();
+        let k = self.value(key).view().into_dimensionality::<Ix3>().unwrap();
+        let v = self.value(value).view().into_dimensionality::<Ix3>().unwrap();
+        let batch_size = q.shape()[0];
+
+        // Score every pair in the pattern by the dot product of its query and key
+        let mut weights = Array2::<F>::zeros((batch_size, pattern.len()));
+        for b in 0..batch_size {
+            for i in 0..pattern.queries() {
+                for pair in pattern.row(i) {
+                    weights[[b, pair]] = q.slice(s![b, i, ..]).dot(&k.slice(s![b, pattern.key(pair), ..]));
+                }
+            }
+        }
+        if let Some(bias) = bias {
+            weights += self.value(bias);
+        }
+        if let Some(mask) = mask {
+            let mask = mask.broadcast(weights.raw_dim()).expect("mask must broadcast to the attention scores");
+            Zip::from(&mut weights).and(&mask).for_each(|score, &keep| {
+                if !keep {
+                    *score = F::neg_infinity();
+                }
+            });
+        }
+
+        // Normalise each query's scores, then weight the values of its keys
+        let mut output = Array3::<F>::zeros((batch_size, pattern.queries(), v.shape()[2]));
+        for b in 0..batch_size {
+            for i in 0..pattern.queries() {
+                normalise(weights.slice_mut(s![b, pattern.row(i)]));
+                for pair in pattern.row(i) {
+                    output.slice_mut(s![b, i, ..]).scaled_add(weights[[b, pair]], &v.slice(s![b, pattern.key(pair), ..]));
+                }
+            }
+        }
+        self.push(output.into_dyn(), Op::SparseAttention { query, key, value, bias, pattern: pattern.clone(), weights })
+    }
+
+    /// Mean over the last axis, keeping that axis with a length of one
+    pub fn mean(&mut self, a: Var) -> Var {
+        let value = self.value(a);
+        let last = Axis(value.ndim() - 1);
+        let value = value.mean_axis(last).unwrap().insert_axis(last);
+        self.push(value, Op::Mean(a))
+    }
+
+    /// Back propagates the gradient of `output` through every recorded operation
+    pub fn backward(&self, output: Var, grad: ArrayD<F>) -> Gradients<F> {
+        let mut grads: Vec<Option<ArrayD<F>>> = vec![None; self.nodes.len()];
+        grads[output.0] = Some(standard_layout(grad));
+
+        // Nodes are recorded in the order they were computed, so visiting them
+        // in reverse guarantees a node's gradient is complete before it is used
+        for index in (0..=output.0).rev() {
+            let grad = match grads[index].take() {
+                Some(grad) => grad,
+                None => continue,
+            };
+            let node = &self.nodes[index];
+
+            let mut accumulate = |var: Var, g: ArrayD<F>| {
+                grads[var.0] = Some(match grads[var.0].take() {
+                    Some(existing) => existing + g,
+                    None => g,
+                });
+            };
+
+            match node.op {
+                // Leaf gradients are kept so they can be read once the pass is complete
+                Op::Leaf => accumulate(Var(index), grad),
+                Op::Add(a, b) => {
+                    accumulate(a, unbroadcast(grad.clone(), self.value(a).shape()));
+                    accumulate(b, unbroadcast(grad, self.value(b).shape()));
+                }
+                Op::Sub(a, b) => {
+                    accumulate(a, unbroadcast(grad.clone(), self.value(a).shape()));
+                    accumulate(b, unbroadcast(-grad, self.value(b).shape()));
+                }
+                Op::Mul(a, b) => {
+                    let (x, y) = (self.value(a), self.value(b));
+                    accumulate(a, unbroadcast(&grad * y, x.shape()));
+                    accumulate(b, unbroadcast(&grad * x, y.shape()));
+                }
+                Op::Div(a, b) => {
+                    let (x, y) = (self.value(a), self.value(b));
+                    accumulate(a, unbroadcast(&grad / y, x.shape()));
+                    accumulate(b, unbroadcast(-(&grad * x) / (y * y), y.shape()));
+                }
+                Op::Scale(a, factor) => accumulate(a, grad * factor),
+                Op::MatMul(a, b) => {
+                    let (x, y) = (self.value(a), self.value(b));
+                    accumulate(b, dot_rhs_grad(x, y, &grad));
+                    accumulate(a, dot(&grad, &transpose(y)));
+                }
+                Op::Transpose(a) => accumulate(a, transpose(&grad)),
+                Op::Reshape(a) => {
+                    let shape = self.value(a).raw_dim();
+                    accumulate(a, standard_layout(grad).into_shape(shape).unwrap());
+                }
+                Op::Slice(a, start) => {
+                    // Only the sliced elements affect the output, the rest get no gradient
+                    let mut full = ArrayD::<F>::zeros(self.value(a).raw_dim());
+                    let last = Axis(full.ndim() - 1);
+                    let width = grad.shape()[last.index()];
+                    full.slice_axis_mut(last, Slice::from(start..start + width)).assign(&grad);
+                    accumulate(a, full);
+                }
+                Op::Concatenate(ref parts) => {
+                    // Hand each part back the range of the gradient it filled
+                    let last = Axis(grad.ndim() - 1);
+                    let mut start = 0;
+                    for &part in parts {
+                        let width = self.value(part).shape()[last.index()];
+                        accumulate(part, grad.slice_axis(last, Slice::from(start..start + width)).to_owned());
+                        start += width;
+                    }
+                }
+                Op::Relu(a) => {
+                    let mask = self.value(a).mapv(|x| if x > F::zero() { F::one() } else { F::zero() });
+                    accumulate(a, grad * mask);
+                }
+                Op::Sigmoid(a) => {
+                    let rate = node.value.mapv(|y| y * (F::one() - y));
+                    accumulate(a, grad * rate);
+                }
+                Op::Sqrt(a) => accumulate(a, grad / (&node.value * F::cast(2.0))),
+                Op::Softmax(a) => accumulate(a, softmax_grad(&node.value, grad)),
+                Op::Mean(a) => {
+                    let x = self.value(a);
+                    let n = F::cast(x.shape()[x.ndim() - 1] as f64);
+                    let spread = grad.broadcast(x.raw_dim()).unwrap().mapv(|g| g / n);
+                    accumulate(a, spread);
+                }
+                Op::Attention { query, key, value, bias, ref weights } => {
+                    let (q, k, v) = (self.value(query), self.value(key), self.value(value));
+
+                    // dV = Pᵀ·dO and dP = dO·Vᵀ, then back through the softmax to the scores
+                    accumulate(value, dot(&transpose(weights), &grad));
+                    let scores_grad = softmax_grad(weights, dot(&grad, &transpose(v)));
+                    if let Some(bias) = bias {
+                        accumulate(bias, unbroadcast(scores_grad.clone(), self.value(bias).shape()));
+                    }
+
+                    // dQ = dS·K and dK = dSᵀ·Q
+                    accumulate(query, dot(&scores_grad, k));
+                    accumulate(key, dot(&transpose(&scores_grad), q));
+                }
+                Op::SparseAttention { query, key, value, bias, ref pattern, ref weights } => {
+                    let q = self.value(query).view().into_dimensionality::<Ix3>().unwrap();
+                    let k = self.value(key).view().into_dimensionality::<Ix3>().unwrap();
+                    let v = self.value(value).view().into_dimensionality::<Ix3>().unwrap();
+                    let grad = grad.into_dimensionality::<Ix3>().unwrap();
+                    let (mut query_grad, mut key_grad, mut value_grad) = (Array3::<F>::zeros(q.raw_dim()), Array3::<F>::zeros(k.raw_dim()), Array3::<F>::zeros(v.raw_dim()));
+                    let mut scores_grad = Array2::<F>::zeros(weights.raw_dim());
+
+                    // The same steps as dense attention, one pair at a time
+                    for b in 0..weights.nrows() {
+                        for i in 0..pattern.queries() {
+                            let output_grad = grad.slice(s![b, i, ..]);
+
+                            // dV = Pᵀ·dO and dP = dO·Vᵀ
+                            for pair in pattern.row(i) {
+                                let j = pattern.key(pair);
+                                value_grad.slice_mut(s![b, j, ..]).scaled_add(weights[[b, pair]], &output_grad);
+                                scores_grad[[b, pair]] = output_grad.dot(&v.slice(s![b, j, ..]));
+                            }
+
+                            // Back through the softmax to the scores
+                            let weighted = weights.slice(s![b, pattern.row(i)]).dot(&scores_grad.slice(s![b, pattern.row(i)]));
+                            for pair in pattern.row(i) {
+                                scores_grad[[b, pair]] = weights[[b, pair]] * (scores_grad[[b, pair]] - weighted);
+                            }
+
+                            // dQ = dS·K and dK = dSᵀ·Q
+                            for pair in pattern.row(i) {
+                                let j = pattern.key(pair);
+                                query_grad.slice_mut(s![b, i, ..]).scaled_add(scores_grad[[b, pair]], &k.slice(s![b, j, ..]));
+                                key_grad.slice_mut(s![b, j, ..]).scaled_add(scores_grad[[b, pair]], &q.slice(s![b, i, ..]));
+                            }
+                        }
+                    }
+
+                    if let Some(bias) = bias {
+                        accumulate(bias, unbroadcast(scores_grad.into_dyn(), self.value(bias).shape()));
+                    }
+                    accumulate(query, query_grad.into_dyn());
+                    accumulate(key, key_grad.into_dyn());
+                    accumulate(value, value_grad.into_dyn());
+                }
+            }
+        }
+
+        Gradients { grads }
+    }
+}
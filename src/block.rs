@@ -1,11 +1,39 @@
-/// A trait for a block in the transformer
-pub trait Block {
+use crate::float::Float;
+use crate::parameter::Parameter;
+
+/// A trait for a block in the transformer, computed in the float type `F`
+pub trait Block<F: Float = f32> {
     type Input;
     type Output;
 
     /// Forward propagates input through the block
     fn forward_propagate(&mut self, value: Self::Input) -> Self::Output;
 
-    /// Back propagates error through the block
+    /// Computes the same output as `forward_propagate` without storing anything
+    /// for back propagation, so a trained block can be shared between threads.
+    /// Blocks built on the autograd tape record the calculation on a local tape.
+    fn predict(&self, value: Self::Input) -> Self::Output;
+
+    /// Back propagates error through the block, accumulating the gradients
+    /// of its parameters without updating them
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input;
+
+    /// Returns every trainable parameter in the block, named by its path
+    /// through the block's hierarchy, e.g. `encoder.0.attn.group.2.query`
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        vec![]
+    }
+
+    /// Returns mutable references to every trainable parameter in the block,
+    /// in the same order and with the same names as `parameters`
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        vec![]
+    }
+
+    /// Resets the accumulated gradients of every parameter
+    fn zero_grad(&mut self) {
+        for (_, param) in self.parameters_mut() {
+            param.zero_grad();
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use serde::{Serialize, Deserialize};
+use crate::float::Float;
+use crate::transformer::TransformerConfig;
+
+/// The version of the checkpoint format written by this build. It changes whenever
+/// the names or shapes of the saved tensors do, so older files are rejected by
+/// version rather than failing on their first mismatched tensor.
+///
+/// 2: per-head key, query and value projections on an equal slice of each word vector
+///
+/// 3: a per-word attention output projection
+///
+/// 4: per-word feed-forward layers and a classifier over the mean word vector
+///
+/// 5: attention parameters named by key/value group, `group.{i}`, rather than `head.{i}`
+pub const FORMAT_VERSION: u32 = 5;
+
+/// A single named tensor stored in a checkpoint
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")] // Float already requires serde support
+pub struct TensorRecord<F: Float = f32> {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub data: Vec<F>,
+}
+
+/// The contents of a checkpoint file
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")] // Float already requires serde support
+pub struct Checkpoint<F: Float = f32> {
+    pub version: u32,
+    pub config: TransformerConfig,
+    pub tensors: Vec<TensorRecord<F>>,
+}
+
+/// The part of a checkpoint read before the rest, so that files of other
+/// versions are rejected by version even if their body has a different layout
+#[derive(Deserialize)]
+struct CheckpointHeader {
+    version: u32,
+}
+
+/// The reasons a checkpoint can fail to save or load
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    Invalid(String),
+    Version { found: u32, expected: u32 },
+    MissingTensor(String),
+    UnexpectedTensor(String),
+    ShapeMismatch { name: String, expected: Vec<usize>, found: Vec<usize> },
+    EmbeddingMismatch { word: String, expected: usize, found: usize },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "checkpoint could not be accessed: {}", err),
+            CheckpointError::Format(err) => write!(f, "checkpoint is not valid: {}", err),
+            CheckpointError::Invalid(reason) => write!(f, "checkpoint is not valid: {}", reason),
+            CheckpointError::Version { found, expected } => write!(f, "checkpoint has format version {}, expected {}", found, expected),
+            CheckpointError::MissingTensor(name) => write!(f, "checkpoint is missing tensor `{}`", name),
+            CheckpointError::UnexpectedTensor(name) => write!(f, "checkpoint has unexpected tensor `{}`", name),
+            CheckpointError::ShapeMismatch { name, expected, found } => write!(f, "tensor `{}` has shape {:?}, expected {:?}", name, found, expected),
+            CheckpointError::EmbeddingMismatch { word, expected, found } => write!(f, "embedding for `{}` has {} dimensions, expected {}", word, found, expected),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(err: std::io::Error) -> CheckpointError {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(err: serde_json::Error) -> CheckpointError {
+        CheckpointError::Format(err)
+    }
+}
+
+impl<F: Float> Checkpoint<F> {
+    /// Writes the checkpoint to a file
+    pub fn write(&self, path: &str) -> Result<(), CheckpointError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Reads a checkpoint from a file, rejecting other format versions
+    pub fn read(path: &str) -> Result<Checkpoint<F>, CheckpointError> {
+        let contents = fs::read_to_string(path)?;
+        let header: CheckpointHeader = serde_json::from_str(&contents)?;
+        if header.version != FORMAT_VERSION {
+            return Err(CheckpointError::Version { found: header.version, expected: FORMAT_VERSION });
+        }
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
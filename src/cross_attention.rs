@@ -0,0 +1,119 @@
+use ndarray::{Array2, Array3, ArrayD, Ix3};
+use crate::autograd::{Tape, Var};
+use crate::block::Block;
+use crate::float::Float;
+use crate::initializer::Initializer;
+use crate::mask::cross_attention_mask;
+use crate::parameter::Parameter;
+use crate::projection::{scale_queries, ProjectionVars, Projections};
+use rand::Rng;
+
+// Defines cross-attention struct
+pub struct CrossAttention<F: Float = f32> {
+    input: (Array3::<F>, Array3::<F>),
+    tape: Tape<F>,
+    query_input_var: Var,
+    context_var: Var,
+    output_var: Var,
+    projection_vars: ProjectionVars,
+    params: Projections<F>,
+}
+
+impl<F: Float> CrossAttention<F> {
+    /// Create a new cross-attention block with the given parameters. The queries
+    /// come from a sequence of `rows` words and the keys and values from a context
+    /// of `context_rows` words, such as an encoder's output, both `cols` wide.
+    pub fn new<R: Rng + ?Sized>(rows: usize, context_rows: usize, cols: usize, head_size: usize, init: Initializer, rng: &mut R) -> CrossAttention<F> {
+        let input = (Array3::<F>::zeros((1, rows, cols)), Array3::<F>::zeros((1, context_rows, cols)));
+        let params = Projections::new(cols, head_size, 1, init, rng);
+
+        let block: CrossAttention<F> = CrossAttention {
+            input,
+            tape: Tape::new(),
+            query_input_var: Var::default(),
+            context_var: Var::default(),
+            output_var: Var::default(),
+            projection_vars: ProjectionVars::default(),
+            params
+        };
+
+        block
+    }
+
+    /// Forward propagates a sequence and its context through the block, ignoring the
+    /// words of either marked as padding. Padded words of the sequence get an output
+    /// of zero and padded words of the context get no attention weight.
+    pub fn forward_propagate_masked(&mut self, value: (Array3<F>, Array3<F>), padding: Option<(&Array2<bool>, &Array2<bool>)>) -> Array3<F> {
+        self.input = value;
+
+        // Record the attention calculation on a fresh tape, keeping it for back propagation
+        let mut tape = Tape::new();
+        self.query_input_var = tape.leaf(self.input.0.clone().into_dyn());
+        self.context_var = tape.leaf(self.input.1.clone().into_dyn());
+        (self.output_var, self.projection_vars) = self.record(&mut tape, self.query_input_var, self.context_var, padding);
+        self.tape = tape;
+
+        self.tape.value(self.output_var).clone().into_dimensionality::<Ix3>().unwrap()
+    }
+
+    /// Computes the same output as `forward_propagate_masked` without storing anything
+    pub fn predict_masked(&self, value: (Array3<F>, Array3<F>), padding: Option<(&Array2<bool>, &Array2<bool>)>) -> Array3<F> {
+        let mut tape = Tape::new();
+        let query_input = tape.leaf(value.0.into_dyn());
+        let context = tape.leaf(value.1.into_dyn());
+        let (output, _) = self.record(&mut tape, query_input, context, padding);
+        tape.value(output).clone().into_dimensionality::<Ix3>().unwrap()
+    }
+
+    /// Records the attention calculation on a tape, returning the output and
+    /// the handles of the key, query and value matrices
+    fn record(&self, tape: &mut Tape<F>, query_input: Var, context: Var, padding: Option<(&Array2<bool>, &Array2<bool>)>) -> (Var, ProjectionVars) {
+        let vars = self.params.leaves(tape);
+
+        // The queries come from the sequence, the keys and values from the context
+        let (queries, keys, values) = self.params.project(tape, vars, query_input, context);
+        let queries = scale_queries(tape, queries, self.params.head_size());
+
+        // Weight the context's value vectors by the softmax of the dot product of every
+        // word of the sequence with every word of the context, softmax(QKᵀ/√d_k)V
+        let mask: Option<ArrayD<bool>> = padding.map(|(queries, context)| cross_attention_mask(queries, context));
+        let output = tape.attention(queries, keys, values, None, mask.as_ref());
+
+        (output, vars)
+    }
+}
+
+impl<F: Float> Block<F> for CrossAttention<F> {
+    type Input = (Array3<F>, Array3<F>);
+    type Output = Array3<F>;
+
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        self.forward_propagate_masked(value, None)
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        self.predict_masked(value, None)
+    }
+
+    /// Returns the error of the sequence and of the context
+    fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
+        // Back propagate the error through the recorded attention calculation
+        let grads = self.tape.backward(self.output_var, error.into_dyn());
+
+        // Accumulate the gradients of the key, query and value matrices
+        self.params.accumulate(&grads, &self.tape, self.projection_vars);
+
+        (
+            grads.get(self.query_input_var, &self.tape).into_dimensionality::<Ix3>().unwrap(),
+            grads.get(self.context_var, &self.tape).into_dimensionality::<Ix3>().unwrap(),
+        )
+    }
+
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        self.params.parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        self.params.parameters_mut()
+    }
+}
\ No newline at end of file
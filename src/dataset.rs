@@ -1,6 +1,9 @@
-use ndarray::Array1;
+use ndarray::{stack, Array1, Array2, Axis};
 use std::collections::HashMap;
 
+/// The word used to pad reviews which are shorter than the review size
+pub const PAD: &str = "";
+
 pub struct Review {
     pub review: Array1<String>,
     pub sentiment: f32,
@@ -28,7 +31,7 @@ fn clean_review(mut review: String, word_embeddings: HashMap<String, Vec<f32>>)
             // If we were inside a word, it has ended, so process it
             if in_word {
                 in_word = false;
-                if current_word != "" {
+                if !current_word.is_empty() {
                     current_word = current_word.to_lowercase();
                     if word_embeddings.contains_key(&current_word) {
                         current_word.push(' ');
@@ -41,7 +44,7 @@ fn clean_review(mut review: String, word_embeddings: HashMap<String, Vec<f32>>)
     }
 
     // Process the last word if there is one
-    if current_word != "" {
+    if !current_word.is_empty() {
         current_word = current_word.to_lowercase();
         if word_embeddings.contains_key(&current_word) {
             current_word.push(' ');
@@ -51,7 +54,7 @@ fn clean_review(mut review: String, word_embeddings: HashMap<String, Vec<f32>>)
     clean_review.to_string()
 }
 
-/// Pads the review with empty strings to the desired length
+/// Pads the review with `PAD` to the desired length
 fn pad_review(review: String, review_size: usize) -> Array1<String> {
     let words: Vec<&str> = review.split_whitespace().collect();
     let mut padded_review = Vec::with_capacity(review_size);
@@ -60,7 +63,7 @@ fn pad_review(review: String, review_size: usize) -> Array1<String> {
         let word = if i < words.len() {
             words[i].to_string()
         } else {
-            "".to_string()
+            PAD.to_string()
         };
 
         padded_review.push(word);
@@ -83,4 +86,12 @@ pub fn load_imdb_dataset(path: &str, review_size: usize, word_embeddings: HashMa
         imdb_dataset.push(imdb_review);
     }
     imdb_dataset
-}
\ No newline at end of file
+}
+
+/// Stacks reviews into a batch of words, one row per review, and their sentiments
+pub fn collate(reviews: &[&Review]) -> (Array2<String>, Array1<f32>) {
+    let rows: Vec<_> = reviews.iter().map(|example| example.review.view()).collect();
+    let words = stack(Axis(0), &rows).unwrap();
+    let sentiments = Array1::from_iter(reviews.iter().map(|example| example.sentiment));
+    (words, sentiments)
+}
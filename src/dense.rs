@@ -1,143 +1,150 @@
-use ndarray::{Array1, Array2};
+use ndarray::{Array1, Array2, Ix2};
+use crate::autograd::{Tape, Var};
 use crate::block::Block;
-use crate::LR;
-use rand_distr::{Distribution, Normal};
+use crate::float::Float;
+use crate::initializer::Initializer;
+use crate::parameter::Parameter;
+use rand::Rng;
 
 // Defines struct for storing dense parameters
-pub struct DenseParams {
-    weights: Vec<Array2::<f32>>,
-    biases: Vec<Array1::<f32>>,
+pub struct DenseParams<F: Float = f32> {
+    weights: Vec<Parameter<F>>,
+    biases: Vec<Parameter<F>>,
 }
 
 // Defines dense layer struct
-pub struct Dense {
-    input: Array1::<f32>,
+pub struct Dense<F: Float = f32> {
+    input: Array2::<F>,
     pub input_size: usize,
+    layer_sizes: Array1<usize>,
     linear: bool,
     classifier: bool,
-    layer: Vec<Array1::<f32>>,
-    error: Vec<Array1::<f32>>,
-    params: DenseParams,
+    tape: Tape<F>,
+    input_var: Var,
+    output_var: Var,
+    weight_vars: Vec<Var>,
+    bias_vars: Vec<Var>,
+    params: DenseParams<F>,
 }
 
-impl Dense {
-    /// Create a new self-attention block with the given parameters
-    pub fn new(layer_sizes: Array1<usize>, linear: bool, classifier: bool) -> Dense {
-        let input = Array1::<f32>::zeros(layer_sizes[0]);
-        let mut layer = vec![];
-        let mut error = vec![];
+impl<F: Float> Dense<F> {
+    /// Create a new dense block with the given parameters
+    pub fn new<R: Rng + ?Sized>(layer_sizes: Array1<usize>, linear: bool, classifier: bool, weight_init: Initializer, bias_init: Initializer, rng: &mut R) -> Dense<F> {
+        let input = Array2::<F>::zeros((1, layer_sizes[0]));
         let mut weights = vec![];
-        let mut biases = vec![Array1::<f32>::zeros(0)];
+        let mut biases = vec![];
 
         for i in 0..layer_sizes.len()-1 {
-            let normal = Normal::new(0.0, (2.0 / layer_sizes[i] as f32).sqrt()).unwrap();
-            let mut layer_weights = Array2::<f32>::zeros((layer_sizes[i],layer_sizes[i+1]));
-            let mut layer_biases = Array1::<f32>::zeros(layer_sizes[i+1]);
-
-            // Use He initialisation by using a mean of 0.0 and a standard deviation of sqrt(2/n)
-            layer_weights.mapv_inplace(|_| normal.sample(&mut rand::thread_rng()));
-            layer_biases.mapv_inplace(|_| normal.sample(&mut rand::thread_rng()));
-
-            weights.push(layer_weights);
-            biases.push(layer_biases);
-            layer.push(Array1::<f32>::zeros(layer_sizes[i]));
-            error.push(Array1::<f32>::zeros(layer_sizes[i]));
+            // Each layer's fan in and fan out are the sizes of the layers either side of it
+            let (fan_in, fan_out) = (layer_sizes[i], layer_sizes[i+1]);
+            weights.push(Parameter::new(weight_init.initialize(&[fan_in, fan_out], fan_in, fan_out, rng)));
+            biases.push(Parameter::new(bias_init.initialize(&[fan_out], fan_in, fan_out, rng)));
         }
 
-        layer.push(Array1::<f32>::zeros(layer_sizes[layer_sizes.len()-1]));
-        error.push(Array1::<f32>::zeros(layer_sizes[layer_sizes.len()-1]));
-
         let params = DenseParams { weights, biases };
 
-        let block: Dense = Dense {
+        let block: Dense<F> = Dense {
             input,
             input_size: layer_sizes[0],
+            layer_sizes,
             linear,
             classifier,
-            layer,
-            error,
+            tape: Tape::new(),
+            input_var: Var::default(),
+            output_var: Var::default(),
+            weight_vars: vec![],
+            bias_vars: vec![],
             params
         };
 
         block
     }
-}
-
-/// Sigmoid activation function
-pub fn sigmoid(x: f32) -> f32 {
-    1.0 / (1.0 + (-x).exp())
-}
-
-/// Inverse derivative of the sigmoid function
-pub fn inv_deriv_sigmoid(x: f32) -> f32 {
-    x * (1.0 - x)
-}
-
-impl Block for Dense {
-    type Input = Array1<f32>;
-    type Output = Array1<f32>;
-
-    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
-        self.input = value;
 
-        // Assign input values to the first layer
-        self.layer[0].assign(&self.input);
+    /// Records the layers on a tape with one row of the input per example,
+    /// returning the output and the handles of each layer's weights and biases
+    fn record(&self, tape: &mut Tape<F>, input: Var) -> (Var, Vec<Var>, Vec<Var>) {
+        let mut weight_vars = vec![];
+        let mut bias_vars = vec![];
+        let mut layer = input;
 
         // Iterate over the layers, starting from the second layer (index 1)
-        for i in 1..self.layer.len() {
-            // Compute the weighted sum of the previous layer's output
-            let weighted_sum = &self.layer[i - 1].dot(&self.params.weights[i - 1]);
+        for i in 1..self.layer_sizes.len() {
+            let weights = tape.leaf(self.params.weights[i - 1].value.clone());
+            let biases = tape.leaf(self.params.biases[i - 1].value.clone());
+            weight_vars.push(weights);
+            bias_vars.push(biases);
 
-            self.layer[i] = weighted_sum + &self.params.biases[i];
+            // Compute the weighted sum of the previous layer's output
+            let weighted_sum = tape.matmul(layer, weights);
+            layer = tape.add(weighted_sum, biases);
 
             // Apply activation function if not using linear activation
             if !self.linear {
                 if self.classifier {
                     // Apply sigmoid activation function for classifier networks
-                    self.layer[i].mapv_inplace(sigmoid);
+                    layer = tape.sigmoid(layer);
                 } else {
                     // Apply ReLU activation function for non-classifier networks
-                    self.layer[i].mapv_inplace(|x| if x > 0.0 { x } else { 0.0 });
+                    layer = tape.relu(layer);
                 }
             }
         }
+        (layer, weight_vars, bias_vars)
+    }
+}
+
+impl<F: Float> Block<F> for Dense<F> {
+    type Input = Array2<F>;
+    type Output = Array2<F>;
+
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        self.input = value;
+
+        // Record the layers on a fresh tape, keeping it for back propagation
+        let mut tape = Tape::new();
+        self.input_var = tape.leaf(self.input.clone().into_dyn());
+        (self.output_var, self.weight_vars, self.bias_vars) = self.record(&mut tape, self.input_var);
+        self.tape = tape;
 
         // Return the output of the last layer
-        self.layer[self.layer.len() - 1].clone()
+        self.tape.value(self.output_var).clone().into_dimensionality::<Ix2>().unwrap()
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        let mut tape = Tape::new();
+        let input = tape.leaf(value.into_dyn());
+        let (output, _, _) = self.record(&mut tape, input);
+        tape.value(output).clone().into_dimensionality::<Ix2>().unwrap()
     }
 
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
-        // Set the error of the output layer
-        self.error[self.layer.len()-1] = error;
-
-        // Iterate through the layers in reverse order, excluding the output layer
-        for i in 0..self.layer.len()-1 {
-            let index: usize = self.layer.len() - (i+2);
-
-            // Iterate through the neurons in the current layer
-            for j in 0..self.layer[index].len() {
-                self.error[index][j] = 0.0;
-
-                // Iterate through the neurons in the next layer
-                for k in 0..self.layer[index+1].len() {
-                    // Calculate error and update weights
-                    let next_error: f32 = self.error[index+1][k];
-                    self.error[index][j] += self.params.weights[index][[j,k]] * next_error;
-                    self.params.weights[index][[j,k]] -= self.layer[index][j] * next_error * LR;
-                }
-                // The first layer did not have an activation function nor biases
-                if index > 0 {
-                    // Apply the derivative of the relevant activation function
-                    if self.classifier {
-                        self.error[index][j] *= inv_deriv_sigmoid(self.layer[index][j]);
-                    } else if self.layer[index][j] <= 0.0 {
-                        self.error[index][j] = 0.0;
-                    }
-                    self.params.biases[index][j] -= self.error[index][j] * LR;
-                }
-            }
+        // Back propagate the error through the recorded layers
+        let grads = self.tape.backward(self.output_var, error.into_dyn());
+
+        // Accumulate the gradients of the weights and biases of each layer
+        for i in 0..self.weight_vars.len() {
+            self.params.weights[i].accumulate(&grads.get(self.weight_vars[i], &self.tape));
+            self.params.biases[i].accumulate(&grads.get(self.bias_vars[i], &self.tape));
+        }
+
+        grads.get(self.input_var, &self.tape).into_dimensionality::<Ix2>().unwrap()
+    }
+
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        let mut params = vec![];
+        for (i, (weights, biases)) in self.params.weights.iter().zip(self.params.biases.iter()).enumerate() {
+            params.push((format!("layer.{}.weights", i), weights));
+            params.push((format!("layer.{}.biases", i), biases));
         }
+        params
+    }
 
-        self.error[0].clone()
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        let mut params = vec![];
+        for (i, (weights, biases)) in self.params.weights.iter_mut().zip(self.params.biases.iter_mut()).enumerate() {
+            params.push((format!("layer.{}.weights", i), weights));
+            params.push((format!("layer.{}.biases", i), biases));
+        }
+        params
     }
-}
\ No newline at end of file
+}
@@ -1,93 +1,152 @@
-use ndarray::{Array1, Array2};
+use ndarray::{Array1, Array2, Array3, Array4};
 use crate::add_and_norm::AddAndNorm;
 use crate::block::Block;
+use crate::float::Float;
+use crate::initializer::Initializers;
 use crate::multi_headed_attention::MultiHeadedAttention;
 use crate::dense::Dense;
+use crate::parameter::{with_prefix, Parameter};
+use crate::positional_encoder::PositionEncoding;
+use crate::window::Window;
+use rand::Rng;
 
 // Defines multi headed attention and feed forward blocks.
-pub struct EncoderBlockParams {
-    multi_headed: MultiHeadedAttention,
-    feed_forward: Dense,
+pub struct EncoderBlockParams<F: Float = f32> {
+    multi_headed: MultiHeadedAttention<F>,
+    feed_forward: Dense<F>,
 }
 
 // Defines encoder block struct
-pub struct EncoderBlock {
-    input: Array2::<f32>,
-    add_and_norm: AddAndNorm,
-    rows: usize,
+pub struct EncoderBlock<F: Float = f32> {
+    input: Array3::<F>,
+    attention_norm: AddAndNorm<F>,
+    feed_forward_norm: AddAndNorm<F>,
     cols: usize,
-    params: EncoderBlockParams,
+    params: EncoderBlockParams<F>,
 }
 
-impl EncoderBlock {
-    /// Create a new encoder block with the given parameters
-    pub fn new(rows: usize, cols: usize, num_heads: usize, layer_sizes: Array1<usize>) -> EncoderBlock {
-        let multi_headed = MultiHeadedAttention::new(num_heads, rows, cols);
-        let add_and_norm = AddAndNorm::new(rows, cols);
-        let feed_forward = Dense::new(layer_sizes, false, false);
+impl<F: Float> EncoderBlock<F> {
+    /// Create a new encoder block with the given parameters, whose attention heads
+    /// share keys and values in `num_kv_groups` groups. The feed-forward layer is
+    /// applied to each word vector separately, so `layer_sizes` must start and end
+    /// with `cols`, and the block works on any number of words.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<R: Rng + ?Sized>(rows: usize, cols: usize, num_heads: usize, num_kv_groups: usize, layer_sizes: Array1<usize>, positions: PositionEncoding, init: Initializers, rng: &mut R) -> EncoderBlock<F> {
+        let multi_headed = MultiHeadedAttention::new(num_heads, num_kv_groups, rows, cols, false, positions, init, rng);
+        // Each add and norm records its own tape, so the two uses need separate blocks
+        let attention_norm = AddAndNorm::new();
+        let feed_forward_norm = AddAndNorm::new();
+        let feed_forward = Dense::new(layer_sizes, false, false, init.weights, init.biases, rng);
 
         let params = EncoderBlockParams { multi_headed, feed_forward };
 
-        let block: EncoderBlock = EncoderBlock {
-            input: Array2::<f32>::zeros((rows, cols)),
-            rows,
+        let block: EncoderBlock<F> = EncoderBlock {
+            input: Array3::<F>::zeros((1, rows, cols)),
             cols,
-            add_and_norm,
+            attention_norm,
+            feed_forward_norm,
             params
         };
 
         block
     }
-}
 
-impl Block for EncoderBlock {
-    type Input = Array2<f32>;
-    type Output = Array2<f32>;
+    /// Restricts the block's attention to a sliding window, or lets every word attend
+    /// to every other word if `None`
+    pub fn set_window(&mut self, window: Option<Window>) {
+        self.params.multi_headed.set_window(window);
+    }
 
-    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+    /// Forward propagates input through the block, ignoring the words marked as padding
+    pub fn forward_propagate_masked(&mut self, value: Array3<F>, padding: Option<&Array2<bool>>) -> Array3<F> {
         // Set the input value
         self.input = value;
 
         // Perform forward propagation through the multi-headed layer
-        let multi_out = self.params.multi_headed.forward_propagate(self.input.clone());
+        let multi_out = self.params.multi_headed.forward_propagate_masked(self.input.clone(), padding);
 
         // Perform forward propagation through the add-and-norm layer using the input and the output from the multi-headed layer
-        let add_out = self.add_and_norm.forward_propagate((self.input.clone(), multi_out));
-        let add_out_flat = add_out.clone().into_shape(self.rows*self.cols).unwrap();
+        let add_out = self.attention_norm.forward_propagate((self.input.clone(), multi_out));
+        let (batch_size, words) = (add_out.shape()[0], add_out.shape()[1]);
+
+        // The feed-forward layer is applied to every word separately, so each word is a single row
+        let add_out_flat = add_out.clone().into_shape((batch_size*words, self.cols)).unwrap();
 
         // Perform forward propagation through the feed-forward layer using the flattened output from the add-and-norm layer
         let feed_out = self.params.feed_forward.forward_propagate(add_out_flat);
-        let feed_out_sq = feed_out.into_shape([self.rows, self.cols]).unwrap();
+        let feed_out_sq = feed_out.into_shape((batch_size, words, self.cols)).unwrap();
 
         // Perform forward propagation through the add-and-norm layer using the output from the feed-forward layer and the output from the previous add-and-norm layer
-        let output = self.add_and_norm.forward_propagate((add_out, feed_out_sq));
+        let output = self.feed_forward_norm.forward_propagate((add_out, feed_out_sq));
 
         // Return the final output
         output
     }
 
+    /// Computes the same output as `forward_propagate_masked` without storing anything
+    pub fn predict_masked(&self, value: Array3<F>, padding: Option<&Array2<bool>>) -> Array3<F> {
+        self.predict_with_attention(value, padding).0
+    }
+
+    /// Computes the same output as `predict_masked`, along with the attention
+    /// weights of every head in the shape (batch, heads, words, words)
+    pub fn predict_with_attention(&self, value: Array3<F>, padding: Option<&Array2<bool>>) -> (Array3<F>, Array4<F>) {
+        let (multi_out, weights) = self.params.multi_headed.predict_with_attention(value.clone(), padding);
+        let add_out = self.attention_norm.predict((value, multi_out));
+        let (batch_size, words) = (add_out.shape()[0], add_out.shape()[1]);
+        let feed_out = self.params.feed_forward.predict(add_out.clone().into_shape((batch_size*words, self.cols)).unwrap());
+        let feed_out_sq = feed_out.into_shape((batch_size, words, self.cols)).unwrap();
+        (self.feed_forward_norm.predict((add_out, feed_out_sq)), weights)
+    }
+}
+
+impl<F: Float> Block<F> for EncoderBlock<F> {
+    type Input = Array3<F>;
+    type Output = Array3<F>;
+
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        self.forward_propagate_masked(value, None)
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        self.predict_masked(value, None)
+    }
+
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
-        // Backpropagate the error through the `add_and_norm` layer, then reshape
-        let norm_error = self.add_and_norm.back_propagate(error);
-        let flat_error = norm_error.1.into_shape(self.rows * self.cols).unwrap();
+        // Backpropagate the error through the `feed_forward_norm` layer, then reshape
+        let (batch_size, words) = (error.shape()[0], error.shape()[1]);
+        let norm_error = self.feed_forward_norm.back_propagate(error);
+        let flat_error = norm_error.1.into_shape((batch_size*words, self.cols)).unwrap();
 
-        // Backpropagate the flat error through the `feed_forward` layer, then reshape it to a 2D array
+        // Backpropagate the flat error through the `feed_forward` layer, then reshape it to a 3D array
         let feed_flat_error = self.params.feed_forward.back_propagate(flat_error);
-        let feed_error = feed_flat_error.into_shape([self.rows, self.cols]).unwrap();
+        let feed_error = feed_flat_error.into_shape((batch_size, words, self.cols)).unwrap();
 
-        // Combine the error from the `add_and_norm` layer and the `feed_forward` layer
+        // Combine the error from the `feed_forward_norm` layer and the `feed_forward` layer
         let residual_error = &norm_error.0 + &feed_error;
 
-        // Backpropagate the residual error through the `add_and_norm` layer
-        let norm_error2 = self.add_and_norm.back_propagate(residual_error);
+        // Backpropagate the residual error through the `attention_norm` layer
+        let norm_error2 = self.attention_norm.back_propagate(residual_error);
 
         // Backpropagate the error through the `multi_headed` layer
         let multi_headed_error = self.params.multi_headed.back_propagate(norm_error2.1);
 
-        // Combine the error from the previous `add_and_norm` layer and the `multi_headed` layer
+        // Combine the error from the `attention_norm` layer and the `multi_headed` layer
         let prev_error = &norm_error2.0 + &multi_headed_error;
 
         // Return the previous error as the final output
         prev_error
     }
+
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        let mut params = with_prefix("attn", self.params.multi_headed.parameters());
+        params.extend(with_prefix("feed_forward", self.params.feed_forward.parameters()));
+        params
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        let mut params = with_prefix("attn", self.params.multi_headed.parameters_mut());
+        params.extend(with_prefix("feed_forward", self.params.feed_forward.parameters_mut()));
+        params
+    }
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{AddAssign, DivAssign, MulAssign, SubAssign};
+use ndarray::{LinalgScalar, ScalarOperand};
+use num_traits::FromPrimitive;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// A trait for the floating point types the blocks can be computed in, so a
+/// model can be trained in `f32` for speed or `f64` for precision
+pub trait Float:
+    num_traits::Float + FromPrimitive + LinalgScalar + ScalarOperand
+    + AddAssign + SubAssign + MulAssign + DivAssign + Sum
+    + Debug + Display + Default + Send + Sync
+    + Serialize + DeserializeOwned
+{
+    /// The name of this type in the safetensors format
+    const DTYPE: &'static str;
+
+    /// Converts a constant to this type, rounding if necessary
+    fn cast(value: f64) -> Self;
+
+    /// Returns the little-endian bytes of the value
+    fn to_le_bytes(self) -> Vec<u8>;
+}
+
+impl Float for f32 {
+    const DTYPE: &'static str = "F32";
+
+    fn cast(value: f64) -> f32 {
+        value as f32
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        f32::to_le_bytes(self).to_vec()
+    }
+}
+
+impl Float for f64 {
+    const DTYPE: &'static str = "F64";
+
+    fn cast(value: f64) -> f64 {
+        value
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        f64::to_le_bytes(self).to_vec()
+    }
+}
\ No newline at end of file
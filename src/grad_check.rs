@@ -0,0 +1,168 @@
+use ndarray::{Array, Dimension};
+use rand::Rng;
+use crate::block::Block;
+use crate::float::Float;
+
+/// A trait for values whose elements the gradient checker can read and perturb
+pub trait Checkable<F: Float>: Clone {
+    /// Returns every element in logical order
+    fn values(&self) -> Vec<F>;
+
+    /// Returns a copy of this value with its elements replaced, in logical order
+    fn with_values(&self, values: &[F]) -> Self;
+}
+
+impl<F: Float, D: Dimension> Checkable<F> for Array<F, D> {
+    fn values(&self) -> Vec<F> {
+        self.iter().cloned().collect()
+    }
+
+    fn with_values(&self, values: &[F]) -> Self {
+        Array::from_shape_vec(self.raw_dim(), values.to_vec()).unwrap()
+    }
+}
+
+impl<F: Float, A: Checkable<F>, B: Checkable<F>> Checkable<F> for (A, B) {
+    fn values(&self) -> Vec<F> {
+        let mut values = self.0.values();
+        values.extend(self.1.values());
+        values
+    }
+
+    fn with_values(&self, values: &[F]) -> Self {
+        let split = self.0.values().len();
+        (self.0.with_values(&values[..split]), self.1.with_values(&values[split..]))
+    }
+}
+
+/// The result of checking the gradient of one tensor
+#[derive(Debug)]
+pub struct TensorError<F: Float = f32> {
+    pub name: String,
+    pub relative_error: F,
+}
+
+/// Finds the relative error between numerical and analytical gradients,
+/// using the norm of the whole tensor so tiny elements don't dominate
+fn relative_error<F: Float>(numerical: &[F], analytical: &[F]) -> F {
+    let norm = |x: &[F]| x.iter().map(|&e| e * e).sum::<F>().sqrt();
+    let difference: Vec<F> = numerical.iter().zip(analytical).map(|(&n, &a)| n - a).collect();
+    let scale = norm(numerical) + norm(analytical);
+    if scale == F::zero() { F::zero() } else { norm(&difference) / scale }
+}
+
+/// Projects an output onto the checker's random direction, giving a scalar loss
+fn project<F: Float, O: Checkable<F>>(output: &O, direction: &[F]) -> F {
+    output.values().iter().zip(direction).map(|(&o, &d)| o * d).sum()
+}
+
+/// Runs the analytical pass, returning the gradient of every parameter, the
+/// gradient of the input and the random direction the output was projected onto
+#[allow(clippy::type_complexity)]
+fn analytical<F, B, R>(block: &mut B, input: &B::Input, rng: &mut R) -> (Vec<(String, Vec<F>)>, B::Input, Vec<F>)
+where
+    F: Float,
+    B: Block<F>,
+    B::Input: Clone,
+    B::Output: Checkable<F>,
+    R: Rng + ?Sized,
+{
+    let output = block.forward_propagate(input.clone());
+    let direction: Vec<F> = (0..output.values().len()).map(|_| F::cast(rng.gen_range(-1.0..1.0))).collect();
+
+    block.zero_grad();
+    let input_grad = block.back_propagate(output.with_values(&direction));
+    let param_grads = block.parameters().into_iter()
+        .map(|(name, param)| (name, param.grad.iter().cloned().collect()))
+        .collect();
+    block.zero_grad();
+
+    (param_grads, input_grad, direction)
+}
+
+/// Compares numerical and analytical gradients of every parameter of a block,
+/// using central differences with the given step size. The output is projected
+/// onto a random direction drawn from `rng` to give a scalar loss.
+pub fn check_parameters<F, B, R>(block: &mut B, input: B::Input, epsilon: F, rng: &mut R) -> Vec<TensorError<F>>
+where
+    F: Float,
+    B: Block<F>,
+    B::Input: Clone,
+    B::Output: Checkable<F>,
+    R: Rng + ?Sized,
+{
+    let (param_grads, _, direction) = analytical(block, &input, rng);
+    numerical_parameters(block, &input, &direction, param_grads, epsilon)
+}
+
+/// Compares numerical and analytical gradients of the input and every parameter
+/// of a block, using central differences with the given step size. The output is
+/// projected onto a random direction drawn from `rng` to give a scalar loss.
+pub fn check_gradients<F, B, R>(block: &mut B, input: B::Input, epsilon: F, rng: &mut R) -> Vec<TensorError<F>>
+where
+    F: Float,
+    B: Block<F>,
+    B::Input: Checkable<F>,
+    B::Output: Checkable<F>,
+    R: Rng + ?Sized,
+{
+    let (param_grads, input_grad, direction) = analytical(block, &input, rng);
+
+    // Perturb each element of the input in turn
+    let values = input.values();
+    let mut numerical = vec![];
+    for i in 0..values.len() {
+        let mut perturbed = values.clone();
+        perturbed[i] = values[i] + epsilon;
+        let plus = project(&block.forward_propagate(input.with_values(&perturbed)), &direction);
+        perturbed[i] = values[i] - epsilon;
+        let minus = project(&block.forward_propagate(input.with_values(&perturbed)), &direction);
+        numerical.push((plus - minus) / (epsilon + epsilon));
+    }
+
+    let mut errors = vec![TensorError {
+        name: "input".to_string(),
+        relative_error: relative_error(&numerical, &input_grad.values()),
+    }];
+    errors.extend(numerical_parameters(block, &input, &direction, param_grads, epsilon));
+    errors
+}
+
+/// Perturbs each element of every parameter in turn and compares the result with the analytical gradients
+fn numerical_parameters<F, B>(block: &mut B, input: &B::Input, direction: &[F], param_grads: Vec<(String, Vec<F>)>, epsilon: F) -> Vec<TensorError<F>>
+where
+    F: Float,
+    B: Block<F>,
+    B::Input: Clone,
+    B::Output: Checkable<F>,
+{
+    let mut errors = vec![];
+    for (index, (name, analytical)) in param_grads.into_iter().enumerate() {
+        let mut numerical = vec![];
+        for i in 0..analytical.len() {
+            let original = nudge(block, index, i, epsilon);
+            let plus = project(&block.forward_propagate(input.clone()), direction);
+            nudge(block, index, i, -(epsilon + epsilon));
+            let minus = project(&block.forward_propagate(input.clone()), direction);
+            set(block, index, i, original);
+            numerical.push((plus - minus) / (epsilon + epsilon));
+        }
+        errors.push(TensorError { relative_error: relative_error(&numerical, &analytical), name });
+    }
+    errors
+}
+
+/// Adds to one element of a parameter, returning its previous value
+fn nudge<F: Float, B: Block<F>>(block: &mut B, index: usize, element: usize, amount: F) -> F {
+    let mut params = block.parameters_mut();
+    let value = &mut params[index].1.value.as_slice_mut().unwrap()[element];
+    let original = *value;
+    *value += amount;
+    original
+}
+
+/// Sets one element of a parameter
+fn set<F: Float, B: Block<F>>(block: &mut B, index: usize, element: usize, value: F) {
+    let mut params = block.parameters_mut();
+    params[index].1.value.as_slice_mut().unwrap()[element] = value;
+}
@@ -0,0 +1,152 @@
+use ndarray::{Array2, ArrayD, IxDyn};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Serialize, Deserialize};
+use crate::float::Float;
+
+/// A scheme for choosing the initial values of a parameter
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Initializer {
+    /// Uniform on ±sqrt(6/(fan_in+fan_out))
+    XavierUniform,
+    /// Normal with a standard deviation of sqrt(2/(fan_in+fan_out))
+    XavierNormal,
+    /// Uniform on ±sqrt(6/fan_in)
+    HeUniform,
+    /// Normal with a standard deviation of sqrt(2/fan_in)
+    HeNormal,
+    /// Normal with a standard deviation of sqrt(1/fan_in)
+    LeCunNormal,
+    /// Normal with the given standard deviation, redrawing values beyond two standard deviations
+    TruncatedNormal { std: f32 },
+    /// All zeros, typically for biases
+    Zeros,
+    /// A random matrix with orthonormal rows or columns
+    Orthogonal,
+}
+
+/// The initializer used for each kind of parameter in a composite block. Each
+/// block's constructor takes the initializers it uses, so blocks built separately
+/// can differ, but every encoder block of a transformer is built alike and the
+/// choice there is per kind of layer rather than per block.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Initializers {
+    /// Key, query, value and output projections of attention
+    pub attention: Initializer,
+    /// Weights of feed-forward layers
+    pub weights: Initializer,
+    /// Weights of the sigmoid classifier
+    pub classifier: Initializer,
+    /// Biases of every dense layer
+    pub biases: Initializer,
+}
+
+impl Default for Initializers {
+    /// Xavier for attention and the classifier, whose outputs feed a softmax and a
+    /// sigmoid rather than a ReLU, He for the ReLU feed-forward layers and zero biases,
+    /// so no unit starts out favoured. Before the schemes were configurable every
+    /// weight and bias was He-normal, with attention's fan in taken as every word of
+    /// the review, so its scale shrank as `num_words` grew.
+    fn default() -> Initializers {
+        Initializers {
+            attention: Initializer::XavierUniform,
+            weights: Initializer::HeNormal,
+            classifier: Initializer::XavierNormal,
+            biases: Initializer::Zeros,
+        }
+    }
+}
+
+impl Initializers {
+    /// Checks the parameters of every initializer
+    pub fn validate(&self) -> Result<(), String> {
+        [self.attention, self.weights, self.classifier, self.biases].iter().try_for_each(Initializer::validate)
+    }
+}
+
+impl Initializer {
+    /// Checks the scheme's own parameters, so that sampling is sure to finish
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Initializer::TruncatedNormal { std } if !(std.is_finite() && std > 0.0) => {
+                Err(format!("truncated normal needs a finite, positive standard deviation, not {}", std))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Create a new parameter value of the given shape. `fan_in` and `fan_out`
+    /// are the number of inputs and outputs of the layer the parameter belongs to.
+    /// Panics if the scheme's parameters are invalid.
+    pub fn initialize<F: Float, R: Rng + ?Sized>(&self, shape: &[usize], fan_in: usize, fan_out: usize, rng: &mut R) -> ArrayD<F> {
+        if let Err(reason) = self.validate() {
+            panic!("{}", reason);
+        }
+
+        // Values are drawn in double precision and rounded to the parameter's type
+        let (fan_in, fan_out) = (fan_in as f64, fan_out as f64);
+        let mut value = ArrayD::<f64>::zeros(IxDyn(shape));
+
+        match *self {
+            Initializer::XavierUniform => uniform(&mut value, (6.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initializer::XavierNormal => normal(&mut value, (2.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initializer::HeUniform => uniform(&mut value, (6.0 / fan_in).sqrt(), rng),
+            Initializer::HeNormal => normal(&mut value, (2.0 / fan_in).sqrt(), rng),
+            Initializer::LeCunNormal => normal(&mut value, (1.0 / fan_in).sqrt(), rng),
+            Initializer::TruncatedNormal { std } => {
+                let std = std as f64;
+                let distribution = Normal::new(0.0, std).unwrap();
+                value.mapv_inplace(|_| loop {
+                    let sample: f64 = distribution.sample(rng);
+                    if sample.abs() <= 2.0 * std {
+                        break sample;
+                    }
+                });
+            }
+            Initializer::Zeros => {}
+            Initializer::Orthogonal => {
+                // Treat any leading axes as rows, so vectors become a single row
+                let cols = *shape.last().unwrap_or(&1);
+                let rows = value.len() / cols.max(1);
+                value = orthogonal(rows, cols, rng).into_shape(IxDyn(shape)).unwrap();
+            }
+        }
+
+        value.mapv(F::cast)
+    }
+}
+
+/// Fills a value with samples from a uniform distribution on ±limit
+fn uniform<R: Rng + ?Sized>(value: &mut ArrayD<f64>, limit: f64, rng: &mut R) {
+    value.mapv_inplace(|_| rng.gen_range(-limit..=limit));
+}
+
+/// Fills a value with samples from a normal distribution with a mean of zero
+fn normal<R: Rng + ?Sized>(value: &mut ArrayD<f64>, std: f64, rng: &mut R) {
+    let distribution = Normal::new(0.0, std).unwrap();
+    value.mapv_inplace(|_| distribution.sample(rng));
+}
+
+/// Creates a random matrix whose rows or columns, whichever are fewer, are orthonormal
+fn orthogonal<R: Rng + ?Sized>(rows: usize, cols: usize, rng: &mut R) -> Array2<f64> {
+    // Orthonormalise the columns of a tall random matrix using Gram-Schmidt
+    let (tall, short) = (rows.max(cols), rows.min(cols));
+    let distribution = Normal::new(0.0, 1.0).unwrap();
+    let mut matrix = Array2::<f64>::from_shape_simple_fn((tall, short), || distribution.sample(rng));
+
+    for j in 0..short {
+        for k in 0..j {
+            let previous = matrix.column(k).to_owned();
+            let projection = matrix.column(j).dot(&previous);
+            matrix.column_mut(j).scaled_add(-projection, &previous);
+        }
+        let norm = matrix.column(j).dot(&matrix.column(j)).sqrt();
+        matrix.column_mut(j).mapv_inplace(|x| x / norm);
+    }
+
+    if rows < cols {
+        matrix.reversed_axes().as_standard_layout().into_owned()
+    } else {
+        matrix
+    }
+}
\ No newline at end of file
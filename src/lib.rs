@@ -1,14 +1,26 @@
-pub const LR: f32 = 0.001;
-
 pub mod run;
 pub mod logger;
 pub mod dataset;
+pub mod mask;
+pub mod window;
+pub mod float;
+pub mod autograd;
 pub mod block;
+pub mod parameter;
+pub mod initializer;
+pub mod loss;
+pub mod optimizer;
+pub mod grad_check;
+pub mod scheduler;
+pub mod projection;
 pub mod self_attention;
+pub mod cross_attention;
 pub mod embedding;
 pub mod dense;
 pub mod multi_headed_attention;
 pub mod add_and_norm;
 pub mod encoder_block;
 pub mod positional_encoder;
-pub mod transformer;
\ No newline at end of file
+pub mod transformer;
+pub mod checkpoint;
+pub mod safetensors;
\ No newline at end of file
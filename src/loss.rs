@@ -0,0 +1,13 @@
+use ndarray::Array1;
+use crate::float::Float;
+
+/// Mean squared error over a batch of predictions
+pub fn mean_squared_error<F: Float>(predictions: &Array1<F>, targets: &Array1<F>) -> F {
+    (predictions - targets).mapv(|x| x * x).mean().unwrap()
+}
+
+/// Derivative of the mean squared error with respect to each prediction,
+/// so gradients are averaged over the batch
+pub fn mean_squared_error_derivative<F: Float>(predictions: &Array1<F>, targets: &Array1<F>) -> Array1<F> {
+    (predictions - targets) * F::cast(2.0 / predictions.len() as f64)
+}
\ No newline at end of file
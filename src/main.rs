@@ -1,4 +1,6 @@
 use rusttransformer::*;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::window::Window;
 use log::LevelFilter;
 use std::io;
 
@@ -27,10 +29,70 @@ fn main() {
     io::stdin().read_line(&mut input).expect("Failed to read input.");
     let num_heads = input.trim().parse().expect("Invalid input.");
 
+    println!("Enter the number of key/value groups (blank for one per head): ");
+    input.clear();
+    io::stdin().read_line(&mut input).expect("Failed to read input.");
+    let num_kv_groups = match input.trim() {
+        "" => None,
+        groups => Some(groups.parse().expect("Invalid input.")),
+    };
+
+    println!("Enter the position encoding (sinusoidal, rotary, alibi or relative): ");
+    input.clear();
+    io::stdin().read_line(&mut input).expect("Failed to read input.");
+    let positions = match input.trim() {
+        "sinusoidal" | "" => PositionEncoding::Sinusoidal,
+        "rotary" => PositionEncoding::Rotary,
+        "alibi" => PositionEncoding::Alibi,
+        "relative" => {
+            println!("Enter the number of relative buckets: ");
+            input.clear();
+            io::stdin().read_line(&mut input).expect("Failed to read input.");
+            let buckets = input.trim().parse().expect("Invalid input.");
+
+            println!("Enter the max relative distance: ");
+            input.clear();
+            io::stdin().read_line(&mut input).expect("Failed to read input.");
+            let max_distance = input.trim().parse().expect("Invalid input.");
+
+            PositionEncoding::RelativeBias { buckets, max_distance }
+        }
+        _ => panic!("Invalid input."),
+    };
+
+    println!("Enter the attention window size (blank for full attention): ");
+    input.clear();
+    io::stdin().read_line(&mut input).expect("Failed to read input.");
+    let window = match input.trim() {
+        "" => None,
+        size => {
+            let size = size.parse().expect("Invalid input.");
+
+            println!("Enter the comma-separated global word positions (blank for none): ");
+            input.clear();
+            io::stdin().read_line(&mut input).expect("Failed to read input.");
+            let global = input.split(',').map(str::trim).filter(|position| !position.is_empty())
+                .map(|position| position.parse().expect("Invalid input."))
+                .collect();
+
+            Some(Window::new(size, global))
+        }
+    };
+
     println!("Enter the hidden layer size: ");
     input.clear();
     io::stdin().read_line(&mut input).expect("Failed to read input.");
     let hidden_layer_size = input.trim().parse().expect("Invalid input.");
 
-    run::run(num_words, dimensionality, num_encoders, num_heads, hidden_layer_size);
+    println!("Enter the batch size: ");
+    input.clear();
+    io::stdin().read_line(&mut input).expect("Failed to read input.");
+    let batch_size = input.trim().parse().expect("Invalid input.");
+
+    println!("Enter the random seed: ");
+    input.clear();
+    io::stdin().read_line(&mut input).expect("Failed to read input.");
+    let seed = input.trim().parse().expect("Invalid input.");
+
+    run::run(num_words, dimensionality, num_encoders, num_heads, num_kv_groups, positions, window, hidden_layer_size, batch_size, seed);
 }
\ No newline at end of file
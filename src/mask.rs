@@ -0,0 +1,27 @@
+use ndarray::{Array2, Array3, ArrayD};
+use crate::dataset::PAD;
+
+/// Marks every word in a batch of reviews which isn't padding, one row per review
+pub fn padding_mask(reviews: &Array2<String>) -> Array2<bool> {
+    reviews.map(|word| word != PAD)
+}
+
+/// Expands a padding mask to the shape of the attention scores, (batch, queries, keys),
+/// so padded words neither attend to nor are attended to by any other word
+pub fn attention_mask(padding: &Array2<bool>) -> ArrayD<bool> {
+    cross_attention_mask(padding, padding)
+}
+
+/// Expands the padding masks of two sequences to the shape of the attention scores
+/// when the first attends to the second, (batch, queries, keys)
+pub fn cross_attention_mask(queries: &Array2<bool>, keys: &Array2<bool>) -> ArrayD<bool> {
+    let (batch_size, query_words) = queries.dim();
+    let key_words = keys.dim().1;
+    Array3::from_shape_fn((batch_size, query_words, key_words), |(b, i, j)| queries[[b, i]] && keys[[b, j]]).into_dyn()
+}
+
+/// Marks the words each word may attend to when it mustn't see any later
+/// word, with one row per query and one column per key
+pub fn causal_mask(words: usize) -> Array2<bool> {
+    Array2::from_shape_fn((words, words), |(i, j)| j <= i)
+}
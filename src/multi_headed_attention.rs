@@ -1,94 +1,180 @@
-use ndarray::{arr1, Array1, Array2, Axis};
+use ndarray::{arr1, concatenate, Array1, Array2, Array3, Array4, Axis};
 use crate::block::Block;
-use crate::self_attention::SelfAttention;
+use crate::self_attention::{KvCache, SelfAttention};
 use crate::dense::Dense;
-
-// Defines attention heads and dense layer.
-pub struct MultiHeadedAttentionParams {
-    heads: Array1::<SelfAttention>,
-    linear: Dense,
+use crate::float::Float;
+use crate::initializer::Initializers;
+use crate::parameter::{with_prefix, Parameter};
+use crate::positional_encoder::{alibi_slopes, PositionEncoding};
+use crate::window::Window;
+use rand::Rng;
+
+// Defines attention heads and dense layer. Each entry of `heads` holds the key and
+// value of one group of query heads, or of a single query head without grouping.
+pub struct MultiHeadedAttentionParams<F: Float = f32> {
+    heads: Array1::<SelfAttention<F>>,
+    linear: Dense<F>,
 }
 
 // Defines multi-headed attention struct
-pub struct MultiHeadedAttention {
-    input: Array2::<f32>,
-    rows: usize,
+pub struct MultiHeadedAttention<F: Float = f32> {
+    input: Array3::<F>,
     cols: usize,
     num_heads: usize,
-    params: MultiHeadedAttentionParams,
+    num_kv_groups: usize,
+    head_size: usize,
+    params: MultiHeadedAttentionParams<F>,
 }
 
-impl MultiHeadedAttention {
-    /// Create a new self-attention block with the given parameters
-    pub fn new(num_heads: usize, rows: usize, cols: usize) -> MultiHeadedAttention {
-        let heads: Array1<SelfAttention> = Array1::from_shape_fn(num_heads, |_| SelfAttention::new(rows, cols));
-        let linear: Dense = Dense::new(arr1(&[rows*cols*num_heads, rows*cols]), true, false);
+impl<F: Float> MultiHeadedAttention<F> {
+    /// Create a new multi-headed attention block with the given parameters. The word
+    /// vectors are split evenly between the heads, so `cols` must be divisible by
+    /// `num_heads`. The query heads are split evenly into `num_kv_groups` groups which
+    /// share a key and value, so `num_heads` must be divisible by `num_kv_groups`: one
+    /// group gives multi-query attention and `num_heads` groups standard attention.
+    /// A causal block masks the scores of every head so no word attends to a later word.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<R: Rng + ?Sized>(num_heads: usize, num_kv_groups: usize, rows: usize, cols: usize, causal: bool, positions: PositionEncoding, init: Initializers, rng: &mut R) -> MultiHeadedAttention<F> {
+        assert!(num_heads > 0 && cols.is_multiple_of(num_heads), "dimensionality {} is not divisible by {} heads", cols, num_heads);
+        assert!(num_kv_groups > 0 && num_heads.is_multiple_of(num_kv_groups), "{} heads can't be split into {} key/value groups", num_heads, num_kv_groups);
+        let head_size = cols / num_heads;
+        let group_size = num_heads / num_kv_groups;
+
+        let mut heads: Array1<SelfAttention<F>> = Array1::from_shape_fn(num_kv_groups, |_| SelfAttention::new(rows, cols, head_size, group_size, causal, positions, init.attention, rng));
+
+        // Each query head penalises distance with its own ALiBi slope
+        let slopes = alibi_slopes(num_heads);
+        for (head, slopes) in heads.iter_mut().zip(slopes.chunks(group_size)) {
+            head.set_alibi_slopes(slopes);
+        }
+
+        // The output projection maps each word's concatenated heads back to a word
+        // vector, sharing its weights between every position in the sequence
+        let linear: Dense<F> = Dense::new(arr1(&[num_heads*head_size, cols]), true, false, init.attention, init.biases, rng);
 
         let params = MultiHeadedAttentionParams { heads, linear };
 
-        let block: MultiHeadedAttention = MultiHeadedAttention {
-            input: Array2::<f32>::zeros((rows, cols)),
-            rows,
+        let block: MultiHeadedAttention<F> = MultiHeadedAttention {
+            input: Array3::<F>::zeros((1, rows, cols)),
             cols,
             num_heads,
+            num_kv_groups,
+            head_size,
             params
         };
 
         block
     }
-}
 
-impl Block for MultiHeadedAttention {
-    type Input = Array2<f32>;
-    type Output = Array2<f32>;
+    /// Restricts every head to attending within a sliding window, or lets every word
+    /// attend to every other word if `None`
+    pub fn set_window(&mut self, window: Option<Window>) {
+        for head in self.params.heads.iter_mut() {
+            head.set_window(window.clone());
+        }
+    }
 
-    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+    /// Forward propagates input through every head, ignoring the words marked as padding
+    pub fn forward_propagate_masked(&mut self, value: Array3<F>, padding: Option<&Array2<bool>>) -> Array3<F> {
         self.input = value;
+        let batch_size = self.input.shape()[0];
 
-        // Initialize an array to store the concatenated outputs from different heads
-        let mut concat_heads = Array1::<f32>::zeros(self.params.linear.input_size);
-    
-        // Variable to keep track of the current index in the concatenated heads array
-        let mut concat_index = 0;
-    
-        // Iterate through each head in the model's parameters
+        // Forward propagate the input through each head in the model's parameters
+        let mut head_outputs = vec![];
         for i in 0..self.params.heads.len() {
-            // Forward propagate the input through the current head
-            let head = self.params.heads[i].forward_propagate(self.input.clone());
-    
-            // Flatten the head output and concatenate it to the concat_heads array
-            for j in 0..self.input.shape()[0] {
-                for k in 0..self.input.shape()[1] {
-                    concat_heads[concat_index] = head[[j, k]];
-                    concat_index += 1;
-                }
-            }
+            head_outputs.push(self.params.heads[i].forward_propagate_masked(self.input.clone(), padding));
         }
 
-        // Forward propagate the concatenated heads through the linear layer
-        let output = self.params.linear.forward_propagate(concat_heads);
+        // Forward propagate every word's concatenated heads through the linear layer
+        let output = self.params.linear.forward_propagate(self.concatenate_heads(head_outputs));
 
         // Reshape the output to match the shape of the input
-        output.into_shape([self.input.shape()[0], self.input.shape()[1]]).unwrap()
+        output.into_shape((batch_size, self.input.shape()[1], self.cols)).unwrap()
+    }
+
+    /// Computes the same output as `forward_propagate_masked` without storing anything
+    pub fn predict_masked(&self, value: Array3<F>, padding: Option<&Array2<bool>>) -> Array3<F> {
+        self.predict_with_attention(value, padding).0
+    }
+
+    /// Computes the same output as `predict_masked`, along with every query head's
+    /// attention weights in the shape (batch, heads, words, words)
+    pub fn predict_with_attention(&self, value: Array3<F>, padding: Option<&Array2<bool>>) -> (Array3<F>, Array4<F>) {
+        let (batch_size, words) = (value.shape()[0], value.shape()[1]);
+        let (head_outputs, head_weights): (Vec<_>, Vec<_>) = self.params.heads.iter()
+            .map(|head| head.predict_with_attention(value.clone(), padding))
+            .unzip();
+        let output = self.params.linear.predict(self.concatenate_heads(head_outputs));
+
+        // Join the groups' weights along the head axis
+        let weight_views: Vec<_> = head_weights.iter().map(|weights| weights.view()).collect();
+        let weights = concatenate(Axis(1), &weight_views).unwrap();
+
+        (output.into_shape((batch_size, words, self.cols)).unwrap(), weights)
+    }
+
+    /// Create a new, empty key/value cache for `predict_step`, one for each key/value
+    /// group, so grouping query heads shrinks the cache as well as the parameters
+    pub fn new_cache(&self, batch_size: usize) -> Vec<KvCache<F>> {
+        self.params.heads.iter().map(|head| head.new_cache(batch_size)).collect()
+    }
+
+    /// Computes the output for the next word of every sequence in the batch, given
+    /// in the shape (batch, 1, cols), adding its keys and values to the cache. Feeding
+    /// a sequence one word at a time gives the same outputs as `predict` on the whole
+    /// sequence. Only causal blocks can use a cache.
+    pub fn predict_step(&self, word: Array3<F>, cache: &mut [KvCache<F>]) -> Array3<F> {
+        assert_eq!(cache.len(), self.num_kv_groups, "the cache must have one entry per key/value group");
+        let batch_size = word.shape()[0];
+        let head_outputs = self.params.heads.iter().zip(cache.iter_mut())
+            .map(|(head, head_cache)| head.predict_step(word.clone(), head_cache))
+            .collect();
+        let output = self.params.linear.predict(self.concatenate_heads(head_outputs));
+        output.into_shape((batch_size, 1, self.cols)).unwrap()
+    }
+
+    /// Concatenates the groups' outputs for each word, one head after another, then
+    /// stacks the words of every example so each word is a single row
+    fn concatenate_heads(&self, head_outputs: Vec<Array3<F>>) -> Array2<F> {
+        let (batch_size, words) = (head_outputs[0].shape()[0], head_outputs[0].shape()[1]);
+        let head_views: Vec<_> = head_outputs.iter().map(|head| head.view()).collect();
+        let concatenated = concatenate(Axis(2), &head_views).unwrap();
+        concatenated.as_standard_layout().to_owned().into_shape((batch_size*words, self.num_heads*self.head_size)).unwrap()
+    }
+}
+
+impl<F: Float> Block<F> for MultiHeadedAttention<F> {
+    type Input = Array3<F>;
+    type Output = Array3<F>;
+
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        self.forward_propagate_masked(value, None)
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        self.predict_masked(value, None)
     }
 
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
-        // Flatten the error tensor into a 1D array
-        let flat_error = error.into_shape(self.rows*self.cols).unwrap();
+        let (batch_size, words) = (error.shape()[0], error.shape()[1]);
+
+        // Flatten the error tensor so each word is a single row
+        let flat_error = error.into_shape((batch_size*words, self.cols)).unwrap();
 
         // Backpropagate the flat error through the linear layer
         let linear_error = self.params.linear.back_propagate(flat_error);
 
         // Initialize an empty array to store the accumulated error from all heads
-        let mut prev_error = Array2::<f32>::zeros((self.rows,self.cols));
+        let mut prev_error = Array3::<F>::zeros((batch_size, words, self.cols));
 
-        // Reshape the linear error into a multi-headed error tensor
-        let multi_headed_error = linear_error.into_shape([self.num_heads,self.rows,self.cols]).unwrap();
+        // Reshape the linear error so each word's error is split between the key/value groups
+        let group_size = self.num_heads / self.num_kv_groups;
+        let multi_headed_error = linear_error.into_shape((batch_size, words, self.num_kv_groups, group_size*self.head_size)).unwrap();
 
-        // Iterate over each head and backpropagate the error
-        for i in 0..self.num_heads {
-            // Extract the error for the current head
-            let head_error = multi_headed_error.index_axis(Axis(0), i).to_owned();
+        // Iterate over each group of heads and backpropagate the error
+        for i in 0..self.num_kv_groups {
+            // Extract the error for the current group
+            let head_error = multi_headed_error.index_axis(Axis(2), i).to_owned();
 
             // Backpropagate the head error through the head layer
             let prev_head_error = self.params.heads[i].back_propagate(head_error);
@@ -100,4 +186,25 @@ impl Block for MultiHeadedAttention {
         // Return the accumulated previous error
         prev_error
     }
+
+    /// Names each key/value group's parameters `group.{i}`. A group's `query` holds
+    /// the queries of its heads side by side, in the shape (cols, heads per group ·
+    /// head size), and its `key` and `value` are shared by those heads.
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        let mut params = vec![];
+        for (i, head) in self.params.heads.iter().enumerate() {
+            params.extend(with_prefix(&format!("group.{}", i), head.parameters()));
+        }
+        params.extend(with_prefix("linear", self.params.linear.parameters()));
+        params
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        let mut params = vec![];
+        for (i, head) in self.params.heads.iter_mut().enumerate() {
+            params.extend(with_prefix(&format!("group.{}", i), head.parameters_mut()));
+        }
+        params.extend(with_prefix("linear", self.params.linear.parameters_mut()));
+        params
+    }
 }
\ No newline at end of file
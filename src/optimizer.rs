@@ -0,0 +1,197 @@
+use std::marker::PhantomData;
+use ndarray::{ArrayD, Zip};
+use crate::block::Block;
+use crate::float::Float;
+use crate::parameter::Parameter;
+
+/// A trait for an update rule which applies accumulated gradients to parameters.
+/// Hyperparameters are always `f32` and are converted to the parameters' type.
+pub trait Optimizer<F: Float = f32> {
+    /// Returns the current learning rate
+    fn learning_rate(&self) -> f32;
+
+    /// Sets the learning rate, for example from a schedule
+    fn set_learning_rate(&mut self, learning_rate: f32);
+
+    /// Prepares the optimizer for a new step, before any parameter is updated
+    fn begin_step(&mut self) {}
+
+    /// Updates a single parameter using its accumulated gradient. The index is the
+    /// parameter's position in the block's parameter list and identifies its state
+    fn update(&mut self, index: usize, param: &mut Parameter<F>);
+
+    /// Applies one optimisation step to every parameter of a block
+    fn step<B: Block<F>>(&mut self, block: &mut B) where Self: Sized {
+        self.begin_step();
+        for (index, (_, param)) in block.parameters_mut().into_iter().enumerate() {
+            self.update(index, param);
+        }
+    }
+}
+
+/// Returns the state stored for a parameter, creating it with zeros on first use
+fn state_for<'a, F: Float>(states: &'a mut Vec<ArrayD<F>>, index: usize, param: &Parameter<F>) -> &'a mut ArrayD<F> {
+    while states.len() <= index {
+        states.push(ArrayD::<F>::zeros(param.value.raw_dim()));
+    }
+    &mut states[index]
+}
+
+// Defines plain stochastic gradient descent
+pub struct Sgd<F: Float = f32> {
+    learning_rate: f32,
+    float: PhantomData<F>,
+}
+
+impl<F: Float> Sgd<F> {
+    /// Create a new gradient descent optimizer with the given learning rate
+    pub fn new(learning_rate: f32) -> Sgd<F> {
+        Sgd { learning_rate, float: PhantomData }
+    }
+}
+
+impl<F: Float> Optimizer<F> for Sgd<F> {
+    fn learning_rate(&self) -> f32 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update(&mut self, _index: usize, param: &mut Parameter<F>) {
+        param.value.scaled_add(F::cast(-self.learning_rate as f64), &param.grad);
+    }
+}
+
+// Defines stochastic gradient descent with momentum
+pub struct Momentum<F: Float = f32> {
+    learning_rate: f32,
+    momentum: f32,
+    velocities: Vec<ArrayD<F>>,
+}
+
+impl<F: Float> Momentum<F> {
+    /// Create a new momentum optimizer with the given learning rate and momentum coefficient
+    pub fn new(learning_rate: f32, momentum: f32) -> Momentum<F> {
+        Momentum { learning_rate, momentum, velocities: vec![] }
+    }
+}
+
+impl<F: Float> Optimizer<F> for Momentum<F> {
+    fn learning_rate(&self) -> f32 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update(&mut self, index: usize, param: &mut Parameter<F>) {
+        // Decay the previous velocity and add the current gradient to it
+        let velocity = state_for(&mut self.velocities, index, param);
+        *velocity *= F::cast(self.momentum as f64);
+        *velocity += &param.grad;
+
+        param.value.scaled_add(F::cast(-self.learning_rate as f64), velocity);
+    }
+}
+
+// Defines the Adam optimizer
+pub struct Adam<F: Float = f32> {
+    learning_rate: f32,
+    beta1: f32,
+    beta2: f32,
+    epsilon: f32,
+    timestep: i32,
+    first_moments: Vec<ArrayD<F>>,
+    second_moments: Vec<ArrayD<F>>,
+}
+
+impl<F: Float> Adam<F> {
+    /// Create a new Adam optimizer with the given learning rate, moment decay rates and epsilon
+    pub fn new(learning_rate: f32, beta1: f32, beta2: f32, epsilon: f32) -> Adam<F> {
+        Adam {
+            learning_rate,
+            beta1,
+            beta2,
+            epsilon,
+            timestep: 0,
+            first_moments: vec![],
+            second_moments: vec![],
+        }
+    }
+}
+
+impl<F: Float> Optimizer<F> for Adam<F> {
+    fn learning_rate(&self) -> f32 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn begin_step(&mut self) {
+        self.timestep += 1;
+    }
+
+    fn update(&mut self, index: usize, param: &mut Parameter<F>) {
+        let (beta1, beta2) = (F::cast(self.beta1 as f64), F::cast(self.beta2 as f64));
+
+        // Update the running averages of the gradient and the squared gradient
+        let first = state_for(&mut self.first_moments, index, param);
+        *first *= beta1;
+        first.scaled_add(F::one() - beta1, &param.grad);
+        let second = state_for(&mut self.second_moments, index, param);
+        *second *= beta2;
+        second.scaled_add(F::one() - beta2, &param.grad.mapv(|g| g * g));
+
+        // Correct the bias towards zero of the running averages
+        let first_correction = F::one() - beta1.powi(self.timestep);
+        let second_correction = F::one() - beta2.powi(self.timestep);
+
+        let rate = F::cast(self.learning_rate as f64);
+        let epsilon = F::cast(self.epsilon as f64);
+        Zip::from(&mut param.value)
+            .and(&self.first_moments[index])
+            .and(&self.second_moments[index])
+            .for_each(|value, &first, &second| {
+                *value -= rate * (first / first_correction) / ((second / second_correction).sqrt() + epsilon);
+            });
+    }
+}
+
+// Defines Adam with decoupled weight decay
+pub struct AdamW<F: Float = f32> {
+    adam: Adam<F>,
+    weight_decay: f32,
+}
+
+impl<F: Float> AdamW<F> {
+    /// Create a new AdamW optimizer with the given learning rate, moment decay rates, epsilon and weight decay
+    pub fn new(learning_rate: f32, beta1: f32, beta2: f32, epsilon: f32, weight_decay: f32) -> AdamW<F> {
+        AdamW { adam: Adam::new(learning_rate, beta1, beta2, epsilon), weight_decay }
+    }
+}
+
+impl<F: Float> Optimizer<F> for AdamW<F> {
+    fn learning_rate(&self) -> f32 {
+        self.adam.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.adam.learning_rate = learning_rate;
+    }
+
+    fn begin_step(&mut self) {
+        self.adam.begin_step();
+    }
+
+    fn update(&mut self, index: usize, param: &mut Parameter<F>) {
+        // Decay the weights directly rather than through the gradient, so the
+        // decay is not rescaled by the adaptive learning rate
+        param.value *= F::cast(1.0 - self.adam.learning_rate as f64 * self.weight_decay as f64);
+        self.adam.update(index, param);
+    }
+}
@@ -0,0 +1,36 @@
+use ndarray::ArrayD;
+use crate::float::Float;
+
+/// A trainable value together with the gradient accumulated for it
+pub struct Parameter<F: Float = f32> {
+    pub value: ArrayD<F>,
+    pub grad: ArrayD<F>,
+}
+
+impl<F: Float> Parameter<F> {
+    /// Create a new parameter with the given initial value and no gradient
+    pub fn new(value: ArrayD<F>) -> Parameter<F> {
+        let grad = ArrayD::<F>::zeros(value.raw_dim());
+        Parameter { value, grad }
+    }
+
+    /// Adds a gradient to the accumulated gradient
+    pub fn accumulate(&mut self, grad: &ArrayD<F>) {
+        self.grad += grad;
+    }
+
+    /// Returns the shape of the parameter
+    pub fn shape(&self) -> &[usize] {
+        self.value.shape()
+    }
+
+    /// Resets the accumulated gradient to zero
+    pub fn zero_grad(&mut self) {
+        self.grad.fill(F::zero());
+    }
+}
+
+/// Prefixes the names of a block's parameters with the name of the block
+pub fn with_prefix<T>(prefix: &str, params: Vec<(String, T)>) -> Vec<(String, T)> {
+    params.into_iter().map(|(name, param)| (format!("{}.{}", prefix, name), param)).collect()
+}
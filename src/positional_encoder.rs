@@ -1,50 +1,149 @@
-use ndarray::Array2;
+use std::ops::Range;
+use ndarray::{Array2, Array3};
+use serde::{Serialize, Deserialize};
+use crate::autograd::{Tape, Var};
 use crate::block::Block;
+use crate::float::Float;
 
-// Defines an add and norm struct
-pub struct PositionalEncoder {
-    input: Array2::<f32>,
+/// How a transformer tells the positions of its words apart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum PositionEncoding {
+    /// Fixed sinusoids added to the word embeddings before the first encoder block
+    #[default]
+    Sinusoidal,
+    /// Rotates the queries and keys of every attention head by angles proportional
+    /// to each word's position, so the scores only depend on relative positions
+    Rotary,
+    /// Subtracts a fixed, per-head multiple of the distance between every pair of
+    /// words from their attention scores (ALiBi)
+    Alibi,
+    /// Adds a learned, per-head bias to the attention scores for each bucket of
+    /// distances between words. Nearby distances get a bucket each and the rest
+    /// share logarithmically sized buckets up to `max_distance`, as in T5.
+    RelativeBias { buckets: usize, max_distance: usize },
+}
+
+// Defines positional encoder struct
+pub struct PositionalEncoder<F: Float = f32> {
+    input: Array3::<F>,
     dimensionality: usize,
+    encoding: PositionEncoding,
 }
 
-impl PositionalEncoder {
-    /// Create a new add and norm block with the given parameters
-    pub fn new(rows: usize, cols: usize) -> PositionalEncoder {
+impl<F: Float> PositionalEncoder<F> {
+    /// Create a new positional encoder for `rows` words of `cols` dimensions. Only sinusoidal
+    /// encodings change the embeddings, the others are applied inside attention.
+    pub fn new(rows: usize, cols: usize, encoding: PositionEncoding) -> PositionalEncoder<F> {
 
-        let block: PositionalEncoder = PositionalEncoder {
-            input: Array2::<f32>::zeros((rows, cols)),
+        let block: PositionalEncoder<F> = PositionalEncoder {
+            input: Array3::<F>::zeros((1, rows, cols)),
             dimensionality: cols,
+            encoding,
         };
 
         block
     }
 }
 
-impl Block for PositionalEncoder {
-    type Input = Array2<f32>;
-    type Output = Array2<f32>;
+/// Returns the ALiBi slope of each of `num_heads` heads, a geometric sequence
+/// from 2^(-8/num_heads) down to 2^-8
+pub fn alibi_slopes(num_heads: usize) -> Vec<f64> {
+    (1..=num_heads).map(|h| f64::powf(2.0, -8.0 * h as f64 / num_heads as f64)).collect()
+}
+
+/// Returns the ALiBi bias, -slope·|i - j|, of every query at the positions i in
+/// `queries` for every key at the positions j from 0 to `keys`
+pub fn alibi_bias<F: Float>(queries: Range<usize>, keys: usize, slope: f64) -> Array2<F> {
+    Array2::from_shape_fn((queries.len(), keys), |(i, j)| F::cast(-slope * (queries.start + i).abs_diff(j) as f64))
+}
+
+/// Returns the bucket of the distance from word i to word j for every query at
+/// the positions i in `queries` and every key at the positions j from 0 to `keys`.
+/// Half the buckets are for later words unless the attention is causal.
+pub fn relative_buckets(queries: Range<usize>, keys: usize, buckets: usize, max_distance: usize, causal: bool) -> Array2<usize> {
+    Array2::from_shape_fn((queries.len(), keys), |(i, j)| relative_bucket(queries.start + i, j, buckets, max_distance, causal))
+}
+
+/// Returns the bucket of the distance from the word at position i to the word at
+/// position j, as in `relative_buckets`
+pub fn relative_bucket(i: usize, j: usize, buckets: usize, max_distance: usize, causal: bool) -> usize {
+    let buckets = if causal { buckets } else { buckets / 2 };
+    let exact = (buckets / 2).max(1);
+
+    // Later words get their own range of buckets after the earlier ones
+    let offset = if !causal && j > i { buckets } else { 0 };
+    let distance = i.abs_diff(j);
+
+    // Small distances get a bucket each, larger ones share logarithmic buckets
+    let bucket = if distance < exact || max_distance <= exact {
+        distance
+    } else {
+        let log_ratio = (distance as f64 / exact as f64).ln() / (max_distance as f64 / exact as f64).ln();
+        exact + (log_ratio * (buckets - exact) as f64) as usize
+    };
+    offset + bucket.min(buckets - 1)
+}
+
+/// Records rotary position embeddings on a tape, rotating each pair of dimensions
+/// (2k, 2k+1) of the vector for word p by the angle p/10000^(2k/d). The first
+/// vector is for the word at position `offset`.
+pub fn rotate<F: Float>(tape: &mut Tape<F>, vectors: Var, offset: usize) -> Var {
+    let shape = tape.value(vectors).shape().to_vec();
+    let (words, size) = (shape[shape.len() - 2], shape[shape.len() - 1]);
+
+    // Calculate the cosine and sine of every word's angle for every dimension
+    let angle = |p: usize, j: usize| (offset + p) as f64 / f64::powf(10000.0, (j - j % 2) as f64 / size as f64);
+    let cos = Array2::from_shape_fn((words, size), |(p, j)| F::cast(angle(p, j).cos()));
+    let sin = Array2::from_shape_fn((words, size), |(p, j)| F::cast(angle(p, j).sin()));
+
+    // Maps each pair (x, y) to (-y, x), so the rotation is x·cos + swap(x)·sin
+    let swap = Array2::from_shape_fn((size, size), |(i, j)| {
+        if j % 2 == 1 && i == j - 1 { F::one() } else if j % 2 == 0 && i == j + 1 { -F::one() } else { F::zero() }
+    });
+
+    let cos = tape.leaf(cos.into_dyn());
+    let sin = tape.leaf(sin.into_dyn());
+    let swap = tape.leaf(swap.into_dyn());
+    let swapped = tape.matmul(vectors, swap);
+    let cos_part = tape.mul(vectors, cos);
+    let sin_part = tape.mul(swapped, sin);
+    tape.add(cos_part, sin_part)
+}
+
+impl<F: Float> Block<F> for PositionalEncoder<F> {
+    type Input = Array3<F>;
+    type Output = Array3<F>;
 
     fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
         self.input = value;  // Set the input value for the layer.
-    
+
+        // The encodings have no parameters, so nothing else needs storing.
+        self.predict(self.input.clone())
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        // Other encodings leave the embeddings untouched.
+        if self.encoding != PositionEncoding::Sinusoidal {
+            return value;
+        }
+
         // Create positional encodings matrix.
-        let mut positional_encodings = Array2::<f32>::zeros((self.input.shape()[0], self.dimensionality));
+        let mut positional_encodings = Array2::<F>::zeros((value.shape()[1], self.dimensionality));
 
         // Iterate over rows of the input.
-        for i in 0..self.input.shape()[0] {
+        for i in 0..value.shape()[1] {
             // Iterate over columns of the input.
             for j in 0..self.dimensionality {
                 // Calculate the angle for positional encoding.
-                let angle = i as f32 / f32::powf(10000.0, 2.0 * j as f32 / self.dimensionality as f32);
+                let angle = F::cast(i as f64 / f64::powf(10000.0, 2.0 * j as f64 / self.dimensionality as f64));
 
                 // Compute sine or cosine based on the column index.
                 positional_encodings[[i,j]] = if j % 2 == 0 { angle.sin() } else { angle.cos() };
             }
         }
 
-        // Add positional encodings to the input.
-        let output = &positional_encodings + &self.input;
-        output  // Return the output.
+        // Add positional encodings to every input in the batch.
+        &positional_encodings + &value  // Return the output.
     }
 
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
@@ -0,0 +1,90 @@
+use crate::autograd::{Gradients, Tape, Var};
+use crate::float::Float;
+use crate::initializer::Initializer;
+use crate::parameter::Parameter;
+use rand::Rng;
+
+/// The key, query and value matrices of an attention block, which project every
+/// word vector down to one key and value of `head_size` dimensions and the queries
+/// of one or more heads
+pub struct Projections<F: Float = f32> {
+    key: Parameter<F>,
+    query: Parameter<F>,
+    value: Parameter<F>,
+}
+
+// Defines the handles of the key, query and value matrices recorded on a tape
+#[derive(Clone, Copy, Default)]
+pub struct ProjectionVars {
+    pub key: Var,
+    pub query: Var,
+    pub value: Var,
+}
+
+impl<F: Float> Projections<F> {
+    /// Create new projections from word vectors of `cols` dimensions, with the
+    /// queries of `query_heads` heads side by side in one matrix
+    pub fn new<R: Rng + ?Sized>(cols: usize, head_size: usize, query_heads: usize, init: Initializer, rng: &mut R) -> Projections<F> {
+        // Each projection maps a single word vector, so the fans don't depend on the sequence
+        // length, and the query heads are initialised as if they were separate matrices
+        Projections {
+            key: Parameter::new(init.initialize(&[cols, head_size], cols, head_size, rng)),
+            query: Parameter::new(init.initialize(&[cols, query_heads*head_size], cols, head_size, rng)),
+            value: Parameter::new(init.initialize(&[cols, head_size], cols, head_size, rng)),
+        }
+    }
+
+    /// Returns the number of dimensions of each key, value and head's query
+    pub fn head_size(&self) -> usize {
+        self.key.value.shape()[1]
+    }
+
+    /// Records the key, query and value matrices on a tape
+    pub fn leaves(&self, tape: &mut Tape<F>) -> ProjectionVars {
+        ProjectionVars {
+            key: tape.leaf(self.key.value.clone()),
+            query: tape.leaf(self.query.value.clone()),
+            value: tape.leaf(self.value.value.clone()),
+        }
+    }
+
+    /// Records the projection of one input into queries and another into keys and
+    /// values, which are the same input for self-attention
+    pub fn project(&self, tape: &mut Tape<F>, vars: ProjectionVars, query_input: Var, context: Var) -> (Var, Var, Var) {
+        let queries = tape.matmul(query_input, vars.query);
+        let keys = tape.matmul(context, vars.key);
+        let values = tape.matmul(context, vars.value);
+        (queries, keys, values)
+    }
+
+    /// Accumulates the gradients of the key, query and value matrices
+    pub fn accumulate(&mut self, grads: &Gradients<F>, tape: &Tape<F>, vars: ProjectionVars) {
+        self.key.accumulate(&grads.get(vars.key, tape));
+        self.query.accumulate(&grads.get(vars.query, tape));
+        self.value.accumulate(&grads.get(vars.value, tape));
+    }
+
+    /// Returns the key, query and value matrices, named as in a block's parameters
+    pub fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        vec![
+            ("key".to_string(), &self.key),
+            ("query".to_string(), &self.query),
+            ("value".to_string(), &self.value),
+        ]
+    }
+
+    /// Returns mutable references to the key, query and value matrices
+    pub fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        vec![
+            ("key".to_string(), &mut self.key),
+            ("query".to_string(), &mut self.query),
+            ("value".to_string(), &mut self.value),
+        ]
+    }
+}
+
+/// Records the scaling of a head's queries by 1/√d_k, so the dot products don't
+/// grow with the head size and saturate the softmax
+pub fn scale_queries<F: Float>(tape: &mut Tape<F>, queries: Var, head_size: usize) -> Var {
+    tape.scale(queries, F::cast(1.0 / (head_size as f64).sqrt()))
+}
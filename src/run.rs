@@ -1,62 +1,105 @@
 use crate::block::Block;
 use ndarray::arr1;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use crate::embedding::load_embeddings;
-use crate::transformer::Transformer;
-use crate::dataset::load_imdb_dataset;
-use log::info;
+use crate::initializer::Initializers;
+use crate::positional_encoder::PositionEncoding;
+use crate::transformer::{Transformer, TransformerConfig};
+use crate::window::Window;
+use crate::dataset::{collate, load_imdb_dataset, Review};
+use crate::loss::{mean_squared_error, mean_squared_error_derivative};
+use crate::optimizer::{Optimizer, Sgd};
+use crate::scheduler::{Constant, LrScheduler, Warmup};
+use log::{error, info};
 
-pub fn run(num_words: usize, dimensionality: usize, num_encoders: usize, num_heads: usize, hidden_layer_size: usize) {
+const CHECKPOINT_PATH: &str = "transformer_checkpoint.json";
+const TEST_SIZE: usize = 200; // Number of examples to test on
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(num_words: usize, dimensionality: usize, num_encoders: usize, num_heads: usize, num_kv_groups: Option<usize>, positions: PositionEncoding, window: Option<Window>, hidden_layer_size: usize, batch_size: usize, seed: u64) {
     let word_embeddings = load_embeddings("word_embeddings.json");
-    let dataset = load_imdb_dataset("imdb_dataset.csv", num_words, word_embeddings.clone());
-    let mut transformer = Transformer::new(num_words, dimensionality, num_encoders, num_heads, arr1(&[num_words*dimensionality,hidden_layer_size,num_words*dimensionality]), word_embeddings);
-    let mut rng = rand::thread_rng();
+    // Every random choice comes from this generator, so a seed always reproduces the same run
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut dataset = load_imdb_dataset("imdb_dataset.csv", num_words, word_embeddings.clone());
+    // Hold out the last examples before shuffling, so every seed is tested on the same reviews
+    let test_set = dataset.split_off(dataset.len() - TEST_SIZE);
+    dataset.shuffle(&mut rng);
+    let config = TransformerConfig {
+        num_words,
+        dimensionality,
+        num_encoders,
+        num_heads,
+        num_kv_groups,
+        layer_sizes: vec![dimensionality, hidden_layer_size, dimensionality],
+        initializers: Initializers::default(),
+        positions,
+        window,
+    };
+    let mut transformer = Transformer::new(config, word_embeddings, &mut rng);
+    let num_params: usize = transformer.parameters().iter().map(|(_, param)| param.value.len()).sum();
+    info!("Training {} parameters.", num_params);
+    let mut optimizer = Sgd::new(0.001);
+    // Warm the learning rate up from zero to avoid unstable early updates
+    let scheduler = Warmup::new(Constant::new(0.001), 1000);
+    let mut step = 0; // Number of updates applied so far
 
     const N: usize = 1000; // Number of values to average over
     let mut prev_n = arr1(&[0.0; N]); // Previous N values
     let mut index = 0; // Index of prev_n
     let test_gaps = 5; // Test runs every N * test_gaps iterations
     let mut test_count = 0; 
-    const TEST_SIZE: usize = 200; // Number of examples to test on
 
     loop {
-        // Select a random example from the dataset excluding the test set
-        let example = &dataset[rng.gen_range(0..dataset.len()-TEST_SIZE)];
+        // Select a batch of random examples from the training set
+        let examples: Vec<&Review> = (0..batch_size).map(|_| &dataset[rng.gen_range(0..dataset.len())]).collect();
+        let (reviews, sentiments) = collate(&examples);
         
-        // Forward propagate the example through the transformer model
-        let val = transformer.forward_propagate(example.review.clone());
-
-        // Calculate the squared difference between the predicted value and the actual sentiment
-        prev_n[index] = (val - example.sentiment).powf(2.0);
-        index += 1;
-
-        if index == N {
-            index = 0;
-            test_count += 1;
-            // Calculate and log the average loss for the current batch
-            info!("{:?}", prev_n.sum() / N as f32);
-
-            // Check if it's time to perform a test on the test set
-            if test_count == test_gaps {
-                // Reset the test count
-                test_count = 0;
-
-                // Create an array to store the test losses
-                let mut test_cost = arr1(&[0.0; TEST_SIZE]);
-
-                // Calculate the loss for each example in the test set
-                for i in 0..TEST_SIZE {
-                    let example = &dataset[dataset.len()-TEST_SIZE+i];
-                    let val = transformer.forward_propagate(example.review.clone());
-                    test_cost[i] = (val - example.sentiment).powf(2.0);
-                }
+        // Forward propagate the batch through the transformer model
+        let val = transformer.forward_propagate(reviews);
+
+        // Back propagate the derivative of the loss, averaged over the batch, through the transformer model
+        transformer.back_propagate(mean_squared_error_derivative(&val, &sentiments));
+
+        // Update the parameters using the accumulated gradients and the scheduled learning rate, then reset them
+        step += 1;
+        optimizer.set_learning_rate(scheduler.learning_rate(step));
+        optimizer.step(&mut transformer);
+        transformer.zero_grad();
+
+        for i in 0..batch_size {
+            // Calculate the squared difference between the predicted value and the actual sentiment
+            prev_n[index] = (val[i] - sentiments[i]).powf(2.0);
+            index += 1;
 
-                // Calculate and log the average loss for the test set
-                info!("TEST - {:?}", test_cost.sum() / TEST_SIZE as f32);
+            if index == N {
+                index = 0;
+                test_count += 1;
+                // Calculate and log the average loss for the last N examples
+                info!("{:?}", prev_n.sum() / N as f32);
+                info!("LR - {:?}", optimizer.learning_rate());
+
+                // Check if it's time to perform a test on the test set
+                if test_count == test_gaps {
+                    // Reset the test count
+                    test_count = 0;
+
+                    // Calculate the average loss for the test set as a single batch
+                    let test_examples: Vec<&Review> = test_set.iter().collect();
+                    let (test_reviews, test_sentiments) = collate(&test_examples);
+                    let test_val = transformer.predict(test_reviews);
+
+                    // Log the average loss for the test set
+                    info!("TEST - {:?}", mean_squared_error(&test_val, &test_sentiments));
+
+                    // Save the progress so far so it isn't lost when the process exits
+                    match transformer.save(CHECKPOINT_PATH) {
+                        Ok(()) => info!("Saved checkpoint to {}.", CHECKPOINT_PATH),
+                        Err(err) => error!("Failed to save checkpoint: {}", err),
+                    }
+                }
             }
         }
-    
-        // Back propagate the sentiment through the transformer model
-        transformer.back_propagate(example.sentiment);
     }
 }
\ No newline at end of file
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fs;
+use ndarray::{ArrayD, IxDyn};
+use serde_json::{json, Map, Value};
+use crate::checkpoint::CheckpointError;
+use crate::float::Float;
+
+// Safetensors files start with the length of their JSON header as a little-endian u64
+const HEADER_SIZE_BYTES: usize = 8;
+
+/// Writes named tensors to a file in the safetensors layout, along with string metadata
+pub fn write_safetensors<F: Float>(path: &str, tensors: &[(String, &ArrayD<F>)], metadata: &HashMap<String, String>) -> Result<(), CheckpointError> {
+    let mut header = Map::new();
+    let mut data: Vec<u8> = vec![];
+
+    // Store every tensor's elements in row-major order, one after another
+    for (name, tensor) in tensors {
+        let begin = data.len();
+        for value in tensor.iter() {
+            data.extend(value.to_le_bytes());
+        }
+        header.insert(name.clone(), json!({
+            "dtype": F::DTYPE,
+            "shape": tensor.shape(),
+            "data_offsets": [begin, data.len()],
+        }));
+    }
+    header.insert("__metadata__".to_string(), json!(metadata));
+
+    // Pad the header with spaces so the data starts on an 8 byte boundary
+    let mut header_bytes = serde_json::to_vec(&Value::Object(header))?;
+    while header_bytes.len() % HEADER_SIZE_BYTES != 0 {
+        header_bytes.push(b' ');
+    }
+
+    let mut bytes = (header_bytes.len() as u64).to_le_bytes().to_vec();
+    bytes.extend(header_bytes);
+    bytes.extend(data);
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Reads every named tensor and the string metadata from a safetensors file.
+/// Tensors stored as F32 or F64 are converted to the requested float type.
+#[allow(clippy::type_complexity)]
+pub fn read_safetensors<F: Float>(path: &str) -> Result<(Vec<(String, ArrayD<F>)>, HashMap<String, String>), CheckpointError> {
+    let bytes = fs::read(path)?;
+    let invalid = |reason: &str| CheckpointError::Invalid(reason.to_string());
+
+    if bytes.len() < HEADER_SIZE_BYTES {
+        return Err(invalid("file is too short for a safetensors header"));
+    }
+    let mut size = [0u8; HEADER_SIZE_BYTES];
+    size.copy_from_slice(&bytes[..HEADER_SIZE_BYTES]);
+    // A corrupt header size could overflow, which is as invalid as one past the end of the file
+    let header_end = usize::try_from(u64::from_le_bytes(size)).ok()
+        .and_then(|header_size| header_size.checked_add(HEADER_SIZE_BYTES))
+        .filter(|&header_end| header_end <= bytes.len())
+        .ok_or_else(|| invalid("header is longer than the file"))?;
+
+    let header: Map<String, Value> = serde_json::from_slice(&bytes[HEADER_SIZE_BYTES..header_end])?;
+    let data = &bytes[header_end..];
+
+    let mut tensors = vec![];
+    let mut metadata = HashMap::new();
+    for (name, entry) in header {
+        if name == "__metadata__" {
+            metadata = serde_json::from_value(entry)?;
+            continue;
+        }
+
+        let dtype = entry["dtype"].as_str().ok_or_else(|| invalid("tensor has no dtype"))?;
+        let element_size = match dtype {
+            "F32" => 4,
+            "F64" => 8,
+            _ => return Err(CheckpointError::Invalid(format!("tensor `{}` has unsupported dtype {}", name, dtype))),
+        };
+        let shape: Vec<usize> = serde_json::from_value(entry["shape"].clone())?;
+        let offsets: [usize; 2] = serde_json::from_value(entry["data_offsets"].clone())?;
+        let size = shape.iter().try_fold(element_size, |size: usize, &len| size.checked_mul(len));
+        if offsets[0] > offsets[1] || offsets[1] > data.len() || Some(offsets[1] - offsets[0]) != size {
+            return Err(CheckpointError::Invalid(format!("tensor `{}` has invalid data offsets", name)));
+        }
+
+        // Decode the little-endian elements of the tensor
+        let values = data[offsets[0]..offsets[1]]
+            .chunks_exact(element_size)
+            .map(|chunk| match element_size {
+                4 => F::cast(f32::from_le_bytes(chunk.try_into().unwrap()) as f64),
+                _ => F::cast(f64::from_le_bytes(chunk.try_into().unwrap())),
+            })
+            .collect();
+        tensors.push((name, ArrayD::from_shape_vec(IxDyn(&shape), values).unwrap()));
+    }
+
+    Ok((tensors, metadata))
+}
@@ -0,0 +1,120 @@
+use std::f32::consts::PI;
+
+/// A trait for a learning rate schedule, driven by the training step counter.
+/// Steps are counted from one.
+pub trait LrScheduler {
+    /// Returns the learning rate to use at the given training step
+    fn learning_rate(&self, step: usize) -> f32;
+}
+
+// Defines a learning rate which never changes
+pub struct Constant {
+    rate: f32,
+}
+
+impl Constant {
+    /// Create a new constant schedule with the given learning rate
+    pub fn new(rate: f32) -> Constant {
+        Constant { rate }
+    }
+}
+
+impl LrScheduler for Constant {
+    fn learning_rate(&self, _step: usize) -> f32 {
+        self.rate
+    }
+}
+
+// Defines a linear warmup from zero into another schedule
+pub struct Warmup<S: LrScheduler> {
+    schedule: S,
+    warmup_steps: usize,
+}
+
+impl<S: LrScheduler> Warmup<S> {
+    /// Create a new warmup which increases linearly to the given schedule over `warmup_steps`
+    pub fn new(schedule: S, warmup_steps: usize) -> Warmup<S> {
+        Warmup { schedule, warmup_steps }
+    }
+}
+
+impl<S: LrScheduler> LrScheduler for Warmup<S> {
+    fn learning_rate(&self, step: usize) -> f32 {
+        let rate = self.schedule.learning_rate(step);
+        if step < self.warmup_steps {
+            rate * step as f32 / self.warmup_steps as f32
+        } else {
+            rate
+        }
+    }
+}
+
+// Defines a cosine decay from a base rate down to a minimum rate
+pub struct CosineDecay {
+    base_rate: f32,
+    min_rate: f32,
+    decay_steps: usize,
+}
+
+impl CosineDecay {
+    /// Create a new cosine decay which starts at `base_rate` on the first step and
+    /// reaches `min_rate` after `decay_steps` steps, staying there
+    pub fn new(base_rate: f32, min_rate: f32, decay_steps: usize) -> CosineDecay {
+        assert!(decay_steps > 0, "a cosine decay needs at least one step");
+        CosineDecay { base_rate, min_rate, decay_steps }
+    }
+}
+
+impl LrScheduler for CosineDecay {
+    fn learning_rate(&self, step: usize) -> f32 {
+        // The first step has had no decay
+        let progress = step.saturating_sub(1).min(self.decay_steps) as f32 / self.decay_steps as f32;
+        let cosine = 0.5 * (1.0 + (PI * progress).cos());
+        self.min_rate + (self.base_rate - self.min_rate) * cosine
+    }
+}
+
+// Defines a decay which multiplies the rate by a constant factor at fixed intervals
+pub struct StepDecay {
+    base_rate: f32,
+    gamma: f32,
+    step_size: usize,
+}
+
+impl StepDecay {
+    /// Create a new step decay which multiplies the rate by `gamma` every `step_size`
+    /// steps, so the first decayed step is `step_size + 1`
+    pub fn new(base_rate: f32, gamma: f32, step_size: usize) -> StepDecay {
+        assert!(step_size > 0, "a step decay needs at least one step between decays");
+        StepDecay { base_rate, gamma, step_size }
+    }
+}
+
+impl LrScheduler for StepDecay {
+    fn learning_rate(&self, step: usize) -> f32 {
+        // Count the steps already taken, so the first `step_size` steps use the base rate
+        self.base_rate * self.gamma.powi((step.saturating_sub(1) / self.step_size) as i32)
+    }
+}
+
+// Defines the schedule from "Attention Is All You Need"
+pub struct InverseSqrt {
+    dimensionality: usize,
+    warmup_steps: usize,
+}
+
+impl InverseSqrt {
+    /// Create a new inverse square root schedule for a model of the given dimensionality
+    pub fn new(dimensionality: usize, warmup_steps: usize) -> InverseSqrt {
+        InverseSqrt { dimensionality, warmup_steps }
+    }
+}
+
+impl LrScheduler for InverseSqrt {
+    fn learning_rate(&self, step: usize) -> f32 {
+        // Increase linearly during warmup, then decay proportionally to 1/sqrt(step)
+        let step = step.max(1) as f32;
+        let warmup = self.warmup_steps as f32;
+        (self.dimensionality as f32).powf(-0.5) * step.powf(-0.5).min(step * warmup.powf(-1.5))
+    }
+}
@@ -1,202 +1,363 @@
-use ndarray::{Array2, Array3, Axis, ArrayViewMut1};
+use std::ops::Range;
+use ndarray::{concatenate, stack, Array1, Array2, Array3, Array4, ArrayD, Axis, Ix3};
+use crate::autograd::{Tape, Var};
 use crate::block::Block;
-use crate::LR;
-use rand_distr::{Distribution, Normal};
-
-// Defines struct for storing key, query, and value matrices
-pub struct SelfAttentionParams {
-    key: Array2::<f32>,
-    query: Array2::<f32>,
-    value: Array2::<f32>,
+use crate::float::Float;
+use crate::initializer::Initializer;
+use crate::mask::{attention_mask, causal_mask};
+use crate::parameter::Parameter;
+use crate::projection::{scale_queries, ProjectionVars, Projections};
+use crate::positional_encoder::{alibi_bias, alibi_slopes, relative_bucket, relative_buckets, rotate, PositionEncoding};
+use crate::window::{Pattern, Window};
+use rand::Rng;
+
+// Defines struct for storing key, query, and value matrices and any relative biases
+pub struct SelfAttentionParams<F: Float = f32> {
+    projections: Projections<F>,
+    relative_bias: Option<Parameter<F>>,
+}
+
+/// The keys and values of the words a causal attention head has already seen,
+/// so each new word can attend to them without recomputing them
+pub struct KvCache<F: Float = f32> {
+    keys: Array3<F>,
+    values: Array3<F>,
+}
+
+impl<F: Float> KvCache<F> {
+    /// Create a new, empty cache for a batch of sequences
+    pub fn new(batch_size: usize, head_size: usize) -> KvCache<F> {
+        KvCache {
+            keys: Array3::zeros((batch_size, 0, head_size)),
+            values: Array3::zeros((batch_size, 0, head_size)),
+        }
+    }
+
+    /// Returns the number of words seen so far
+    pub fn len(&self) -> usize {
+        self.keys.shape()[1]
+    }
+
+    /// Returns true if no words have been seen yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds the keys and values of the next words to the end of the cache
+    fn append(&mut self, keys: &ArrayD<F>, values: &ArrayD<F>) {
+        let keys = keys.view().into_dimensionality::<Ix3>().unwrap();
+        let values = values.view().into_dimensionality::<Ix3>().unwrap();
+        self.keys = concatenate(Axis(1), &[self.keys.view(), keys]).unwrap();
+        self.values = concatenate(Axis(1), &[self.values.view(), values]).unwrap();
+    }
+}
+
+// Defines the handles of the values recorded by one attention calculation
+#[derive(Default)]
+struct Recorded {
+    output: Var,
+    heads: Vec<Var>,
+    projections: ProjectionVars,
+    relative_bias: Option<Var>,
 }
 
 // Defines self-attention struct
-pub struct SelfAttention {
-    input: Array2::<f32>,
-    weights: Array2::<f32>,
-    value_vecs: Array2::<f32>,
-    vec_key_matrix: Array3::<f32>,
-    vec_query_matrix: Array3::<f32>,
-    params: SelfAttentionParams,
+pub struct SelfAttention<F: Float = f32> {
+    input: Array3::<F>,
+    tape: Tape<F>,
+    input_var: Var,
+    recorded: Recorded,
+    query_heads: usize,
+    causal: bool,
+    positions: PositionEncoding,
+    alibi_slopes: Vec<f64>,
+    window: Option<Window>,
+    params: SelfAttentionParams<F>,
 }
 
-impl SelfAttention {
-    /// Create a new self-attention block with the given parameters
-    pub fn new(rows: usize, cols: usize) -> SelfAttention {
-        let input = Array2::<f32>::zeros((rows, cols));
-        let mut key = Array2::<f32>::zeros((cols, cols));
-        let mut query = Array2::<f32>::zeros((cols, cols));
-        let mut value = Array2::<f32>::zeros((cols, cols));
-
-        // Use He initialisation by using a mean of 0.0 and a standard deviation of sqrt(2/n)
-        let normal = Normal::new(0.0, (2.0/(rows*cols) as f32).sqrt()).unwrap();
-        key.mapv_inplace(|_| normal.sample(&mut rand::thread_rng()));
-        query.mapv_inplace(|_| normal.sample(&mut rand::thread_rng()));
-        value.mapv_inplace(|_| normal.sample(&mut rand::thread_rng()));
-
-        // Store intermediary calculations for use in back-propagation
-        let weights = Array2::<f32>::zeros((input.shape()[0], input.shape()[1]));
-        let value_vecs = Array2::<f32>::zeros((input.shape()[0], input.shape()[1]));
-        let vec_key_matrix = Array3::<f32>::zeros((input.shape()[0], input.shape()[0], input.shape()[1]));
-        let vec_query_matrix = Array3::<f32>::zeros((input.shape()[0], input.shape()[0], input.shape()[1]));
-
-        let params = SelfAttentionParams { key, query, value };
-
-        let block: SelfAttention = SelfAttention {
+impl<F: Float> SelfAttention<F> {
+    /// Create a new self-attention block with the given parameters. Every word is
+    /// projected down to one key and value of `head_size` dimensions, which are
+    /// shared by `query_heads` heads with their own queries, and the heads' outputs
+    /// are concatenated. A causal block stops every word attending to any later word.
+    /// Rotary positions need an even head size.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<R: Rng + ?Sized>(rows: usize, cols: usize, head_size: usize, query_heads: usize, causal: bool, positions: PositionEncoding, init: Initializer, rng: &mut R) -> SelfAttention<F> {
+        assert!(query_heads > 0, "a self-attention block needs at least one query head");
+        assert!(positions != PositionEncoding::Rotary || head_size.is_multiple_of(2), "rotary positions need an even head size, not {}", head_size);
+        let input = Array3::<F>::zeros((1, rows, cols));
+
+        // Relative biases start at zero so they don't favour any distance. Every query
+        // head has its own bias for each bucket, stored one head after another.
+        let relative_bias = match positions {
+            PositionEncoding::RelativeBias { buckets, .. } => {
+                assert!(buckets >= 2, "relative biases need at least 2 buckets, not {}", buckets);
+                Some(Parameter::new(ArrayD::zeros(vec![query_heads*buckets])))
+            }
+            _ => None,
+        };
+
+        let params = SelfAttentionParams {
+            projections: Projections::new(cols, head_size, query_heads, init, rng),
+            relative_bias,
+        };
+
+        let block: SelfAttention<F> = SelfAttention {
             input,
-            weights,
-            value_vecs,
-            vec_key_matrix,
-            vec_query_matrix,
+            tape: Tape::new(),
+            input_var: Var::default(),
+            recorded: Recorded::default(),
+            query_heads,
+            causal,
+            positions,
+            alibi_slopes: alibi_slopes(query_heads),
+            window: None,
             params
         };
 
         block
     }
-}
 
-// Apply softmax normalisation to an Array1.
-fn softmax(mut x: ArrayViewMut1<f32>) {
-    // Iterate through the elements of the array to find the highest value.
-    let mut highest = 0.0;
-    for i in 0..x.len() {
-        if x[i] > highest {
-            highest = x[i];
-        }
+    /// Sets how steeply ALiBi penalises distant words for each query head, which
+    /// differs between all the heads of a multi-headed block
+    pub fn set_alibi_slopes(&mut self, slopes: &[f64]) {
+        assert_eq!(slopes.len(), self.query_heads, "there must be one slope per query head");
+        self.alibi_slopes = slopes.to_vec();
     }
-    x.mapv_inplace(|e| e - highest); // Subtract the highest value from each element in the array.
-    x.mapv_inplace(f32::exp); // Apply the exponential function to each element in the array.
 
-    let norm = x.sum(); // Compute the sum of all elements in the array.
+    /// Restricts every word to attending to the words in a sliding window around it,
+    /// and any global words, or lets every word attend to every other word if `None`
+    pub fn set_window(&mut self, window: Option<Window>) {
+        self.window = window;
+    }
 
-    x.mapv_inplace(|e| e / norm); // Divide each element by the sum to normalize the array.
-}
+    /// Forward propagates input through the block, ignoring the words marked as
+    /// padding. Padded words get no attention weight and an output of zero.
+    pub fn forward_propagate_masked(&mut self, value: Array3<F>, padding: Option<&Array2<bool>>) -> Array3<F> {
+        self.input = value;
 
-impl Block for SelfAttention {
-    type Input = Array2<f32>;
-    type Output = Array2<f32>;
+        // Record the attention calculation on a fresh tape, keeping it for back propagation
+        let mut tape = Tape::new();
+        self.input_var = tape.leaf(self.input.clone().into_dyn());
+        self.recorded = self.record(&mut tape, self.input_var, padding);
+        self.tape = tape;
 
-    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
-        self.input = value;
+        self.tape.value(self.recorded.output).clone().into_dimensionality::<Ix3>().unwrap()
+    }
 
-        // Generate context by finding weight vectors
-        self.weights = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
-
-        for i in 0..self.input.shape()[0] {
-            for j in 0..self.input.shape()[0] {
-                // Find similarity of word i and word j by using their dot product
-                let vec_i = self.input.index_axis(Axis(0), i);
-                // Multiply vector inputs by query and key matrices
-                let vec_query = vec_i.dot(&self.params.query);
-                let vec_j = &self.input.index_axis(Axis(0), j);
-                let vec_key = &vec_j.dot(&self.params.key);
-                // Store intermediary values for use in back propagation
-                for k in 0..self.input.shape()[1] {
-                    self.vec_key_matrix[[i,j,k]] = vec_key[k];
-                    self.vec_query_matrix[[i,j,k]] = vec_query[k];
-                }
-                self.weights[[i,j]] = vec_query.dot(vec_key);
-            }
-        }
+    /// Computes the same output as `forward_propagate_masked` without storing anything
+    pub fn predict_masked(&self, value: Array3<F>, padding: Option<&Array2<bool>>) -> Array3<F> {
+        self.predict_with_attention(value, padding).0
+    }
 
-        // Normalize each weight vector using softmax
-        for x in self.weights.axis_iter_mut(Axis(0)) {
-            softmax(x);
-        }
+    /// Computes the same output as `predict_masked`, along with the attention weights
+    /// in the shape (batch, query heads, words, words), where row i of each head holds
+    /// how much word i attends to each word
+    pub fn predict_with_attention(&self, value: Array3<F>, padding: Option<&Array2<bool>>) -> (Array3<F>, Array4<F>) {
+        let mut tape = Tape::new();
+        let input = tape.leaf(value.into_dyn());
+        let recorded = self.record(&mut tape, input, padding);
+
+        // Stack the heads' weights along a new axis after the batch
+        let weights: Vec<Array3<F>> = recorded.heads.iter()
+            .map(|&head| tape.attention_weights(head).expect("each head is recorded by the attention operation").into_dimensionality::<Ix3>().unwrap())
+            .collect();
+        let weight_views: Vec<_> = weights.iter().map(|weights| weights.view()).collect();
+
+        (
+            tape.value(recorded.output).clone().into_dimensionality::<Ix3>().unwrap(),
+            stack(Axis(1), &weight_views).unwrap(),
+        )
+    }
+
+    /// Create a new, empty key/value cache for `predict_step`
+    pub fn new_cache(&self, batch_size: usize) -> KvCache<F> {
+        KvCache::new(batch_size, self.params.projections.head_size())
+    }
+
+    /// Computes the output for the next word of every sequence in the batch, given
+    /// in the shape (batch, 1, cols), adding its key and value to the cache. Feeding
+    /// a sequence one word at a time gives the same outputs as `predict` on the whole
+    /// sequence, without recomputing the keys and values of the earlier words.
+    pub fn predict_step(&self, word: Array3<F>, cache: &mut KvCache<F>) -> Array3<F> {
+        assert!(self.causal, "only a causal block can use a key/value cache, as earlier words can't see later ones");
 
-        // Generate output by calculating value vectors
-        let mut output = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
+        let mut tape = Tape::new();
+        let input = tape.leaf(word.into_dyn());
+        let vars = self.params.projections.leaves(&mut tape);
+        let position = cache.len();
+        let (queries, keys, values) = self.project(&mut tape, input, vars, position);
 
-        // Apply value matrix to each vector before its use
-        self.value_vecs = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
-        for i in 0..self.input.shape()[0] {
-            let vec_i = self.input.index_axis(Axis(0), i);
-            // Multiply each vector inputs by value matrix
-            let vec_value = vec_i.dot(&self.params.value);
-            self.value_vecs.row_mut(i).assign(&vec_value);
+        // The new word attends to every word in the cache and itself, which are never later words
+        cache.append(tape.value(keys), tape.value(values));
+        let keys = tape.leaf(cache.keys.clone().into_dyn());
+        let values = tape.leaf(cache.values.clone().into_dyn());
+        let pattern = self.pattern(position..position + 1, cache.len());
+        let (output, _, _) = self.attend(&mut tape, queries, keys, values, position..position + 1, pattern.as_ref(), None);
+
+        tape.value(output).clone().into_dimensionality::<Ix3>().unwrap()
+    }
+
+    /// Records the attention calculation on a tape, returning the handles of the
+    /// output, each head's output, the parameters and any relative biases
+    fn record(&self, tape: &mut Tape<F>, input: Var, padding: Option<&Array2<bool>>) -> Recorded {
+        let vars = self.params.projections.leaves(tape);
+        let (queries, keys, values) = self.project(tape, input, vars, 0);
+
+        // A window only scores the pairs in its pattern, which already leaves out later words
+        let words = tape.value(input).shape()[1];
+        let pattern = self.pattern(0..words, words);
+        let mask = match &pattern {
+            Some(pattern) => padding.map(|padding| pattern.padding_mask(padding).into_dyn()),
+            None => self.mask(words, padding),
+        };
+        let (output, heads, relative_bias) = self.attend(tape, queries, keys, values, 0..words, pattern.as_ref(), mask.as_ref());
+
+        Recorded { output, heads, projections: vars, relative_bias }
+    }
+
+    /// Records the projection of the input into the queries of every head, keys and
+    /// values, where the first word of the input is at position `offset`
+    fn project(&self, tape: &mut Tape<F>, input: Var, vars: ProjectionVars, offset: usize) -> (Var, Var, Var) {
+        // Multiply every input vector in the batch by the query, key and value matrices
+        let (queries, keys, values) = self.params.projections.project(tape, vars, input, input);
+
+        // Rotary positions rotate the keys here and each head's queries once they're
+        // split apart, leaving the values untouched
+        let keys = match self.positions {
+            PositionEncoding::Rotary => rotate(tape, keys, offset),
+            _ => keys,
+        };
+
+        (queries, keys, values)
+    }
+
+    /// Records every query head attending to the shared keys and values, where the
+    /// queries are for the words at the positions in `positions`, returning the heads'
+    /// outputs concatenated for each word, each head's output and the handle of any
+    /// learned relative biases. A pattern limits the pairs of words which are scored.
+    #[allow(clippy::too_many_arguments)]
+    fn attend(&self, tape: &mut Tape<F>, queries: Var, keys: Var, values: Var, positions: Range<usize>, pattern: Option<&Pattern>, mask: Option<&ArrayD<bool>>) -> (Var, Vec<Var>, Option<Var>) {
+        let head_size = self.params.projections.head_size();
+        let num_keys = tape.value(keys).shape()[1];
+
+        // Each query head looks up its own learned relative biases
+        let (relative_biases, biases) = self.relative_biases(tape, positions.clone(), num_keys, pattern);
+
+        let mut heads = vec![];
+        for i in 0..self.query_heads {
+            let head_queries = tape.slice(queries, i*head_size..(i + 1)*head_size);
+            let head_queries = match self.positions {
+                PositionEncoding::Rotary => rotate(tape, head_queries, positions.start),
+                _ => head_queries,
+            };
+            let head_queries = scale_queries(tape, head_queries, head_size);
+
+            // Weight the value vectors by the softmax of the dot product of every pair
+            // of words plus any position bias, computing every pair at once as softmax(QKᵀ/√d_k + B)V
+            let slope = self.alibi_slopes[i];
+            let bias = match (self.positions, pattern) {
+                (PositionEncoding::Alibi, Some(pattern)) => Some(tape.leaf(pattern.pairs().map(|(i, j)| F::cast(-slope * i.abs_diff(j) as f64)).collect::<Array1<F>>().into_dyn())),
+                (PositionEncoding::Alibi, None) => Some(tape.leaf(alibi_bias(positions.clone(), num_keys, slope).into_dyn())),
+                (PositionEncoding::RelativeBias { .. }, _) => relative_biases.get(i).copied(),
+                _ => None,
+            };
+            heads.push(match pattern {
+                Some(pattern) => tape.sparse_attention(head_queries, keys, values, pattern, bias, mask),
+                None => tape.attention(head_queries, keys, values, bias, mask),
+            });
         }
 
-        for i in 0..self.input.shape()[0] {
-            for j in 0..self.input.shape()[1] {
-                for k in 0..self.input.shape()[0] {
-                    output[[i,j]] += self.value_vecs[[k,j]] * self.weights[[i,k]];
-                }
+        (tape.concatenate(&heads), heads, biases)
+    }
+
+    /// Records the learned relative bias of every query head for the queries at the
+    /// positions in `queries` and the first `keys` words, if there are any, returning
+    /// one bias per head and the handle of the biases of every head and bucket. With a
+    /// pattern, each head has one bias for each of its pairs.
+    fn relative_biases(&self, tape: &mut Tape<F>, queries: Range<usize>, keys: usize, pattern: Option<&Pattern>) -> (Vec<Var>, Option<Var>) {
+        match (self.positions, &self.params.relative_bias) {
+            (PositionEncoding::RelativeBias { buckets, max_distance }, Some(relative_bias)) => {
+                let pair_buckets = match pattern {
+                    Some(pattern) => pattern.pairs().map(|(i, j)| relative_bucket(i, j, buckets, max_distance, self.causal)).collect::<Array1<usize>>().into_dyn(),
+                    None => relative_buckets(queries, keys, buckets, max_distance, self.causal).into_dyn(),
+                };
+
+                // Look up each pair's bias by multiplying a head's biases by a one-hot
+                // encoding of the pair's bucket, so the lookup is differentiable
+                let one_hot = Array2::from_shape_fn((pair_buckets.len(), buckets), |(pair, bucket)| {
+                    if pair_buckets.as_slice().unwrap()[pair] == bucket { F::one() } else { F::zero() }
+                });
+                let one_hot = tape.leaf(one_hot.into_dyn());
+                let biases = tape.leaf(relative_bias.value.clone());
+
+                let head_biases = (0..self.query_heads).map(|i| {
+                    let head = tape.slice(biases, i*buckets..(i + 1)*buckets);
+                    let column = tape.reshape(head, &[buckets, 1]);
+                    let pair_biases = tape.matmul(one_hot, column);
+                    tape.reshape(pair_biases, pair_buckets.shape())
+                }).collect();
+                (head_biases, Some(biases))
             }
+            _ => (vec![], None),
         }
+    }
+
+    /// Returns the pairs of words scored for the queries at the positions in `queries`
+    /// and the first `keys` words, if attention is limited to a window
+    fn pattern(&self, queries: Range<usize>, keys: usize) -> Option<Pattern> {
+        self.window.as_ref().map(|window| window.pattern(queries, keys, self.causal))
+    }
+
+    /// Combines the padding and causal masks, if there are any, in the shape of the attention scores
+    fn mask(&self, words: usize, padding: Option<&Array2<bool>>) -> Option<ArrayD<bool>> {
+        match (padding, self.causal) {
+            (Some(padding), true) => Some(&attention_mask(padding) & &causal_mask(words).into_dyn()),
+            (Some(padding), false) => Some(attention_mask(padding)),
+            (None, true) => Some(causal_mask(words).into_dyn()),
+            (None, false) => None,
+        }
+    }
+}
+
+impl<F: Float> Block<F> for SelfAttention<F> {
+    type Input = Array3<F>;
+    type Output = Array3<F>;
 
-        output
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        self.forward_propagate_masked(value, None)
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        self.predict_masked(value, None)
     }
 
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
-        // Calculate the error with respect to the input values
-        let mut value_error = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
-        // Calculate the weight update rate
-        let mut weight_rate = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
-        
-        // Iterate over the columns (j) of the input
-        for j in 0..self.input.shape()[1] {
-            // Iterate over the rows (k) of the input
-            for k in 0..self.input.shape()[0] {
-                // Iterate over the rows (i) of the input
-                for i in 0..self.input.shape()[0] {
-                    // Accumulate the error by multiplying the error of the current row (i) with the corresponding weight
-                    value_error[[k, j]] += error[[i, j]] * self.weights[[i, k]];
-
-                    // Accumulate the weight update rate by multiplying the error of the current row (i)
-                    // with the corresponding value vector element
-                    weight_rate[[k, i]] += error[[k, j]] * self.value_vecs[[i, j]];
-                }
-                
-                // Iterate over the columns (l) of the input
-                for l in 0..self.input.shape()[1] {
-                    // Update the parameters using the accumulated error, input value, and learning rate
-                    self.params.value[[l, j]] -= value_error[[k, j]] * self.input.index_axis(Axis(0), k)[l] * LR;
-                }
-            }
+        // Back propagate the error through the recorded attention calculation
+        let grads = self.tape.backward(self.recorded.output, error.into_dyn());
+
+        // Accumulate the gradients of the key, query and value matrices
+        self.params.projections.accumulate(&grads, &self.tape, self.recorded.projections);
+        if let (Some(relative_bias), Some(var)) = (&mut self.params.relative_bias, self.recorded.relative_bias) {
+            relative_bias.accumulate(&grads.get(var, &self.tape));
         }
 
-        let mut prev_error = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
-        let mut unnormalised_error = Array2::<f32>::zeros((self.input.shape()[0], self.input.shape()[1]));
-        let unchanged_key = self.params.key.clone();
-        let unchanged_query = self.params.query.clone();
-
-        // Iterate over the rows (i) of the input
-        for i in 0..self.input.shape()[0] {
-            // Iterate over the rows (j) of the input
-            for j in 0..self.input.shape()[0] {
-                // Iterate over the rows (k) of the input
-                for k in 0..self.input.shape()[0] {
-                    // Check if the current row (j) is equal to the current row (k)
-                    if j == k {
-                        // Calculate the rate of change of the output with respect to the input
-                        let output_rate = self.weights[[i, j]] * (1.0 - self.weights[[i, j]]);
-                        unnormalised_error[[i, j]] += output_rate * weight_rate[[i, j]];
-                    } else {
-                        let output_rate = -self.weights[[i, j]] * self.weights[[i, k]];
-                        unnormalised_error[[i, j]] += output_rate * weight_rate[[i, j]];
-                    }
-                }
-
-                // Iterate over the columns (k) of the input
-                for k in 0..self.input.shape()[1] {
-                    // Calculate the rate of change of the key and query vectors
-                    let key_rate = self.vec_query_matrix[[i, j, k]] * unnormalised_error[[i, j]];
-                    let query_rate = self.vec_key_matrix[[i, j, k]] * unnormalised_error[[i, j]];
-
-                    // Iterate over the columns (l) of the input
-                    for l in 0..self.input.shape()[1] {
-                        // Update the key and query parameters using the calculated rates, input values, and learning rate
-                        self.params.key[[l, k]] -= key_rate * self.input[[i, l]] * LR;
-                        self.params.query[[l, k]] -= query_rate * self.input[[i, l]] * LR;
-
-                        // Accumulate the previous error by multiplying the rates with the unchanged key and query values
-                        prev_error[[i, l]] += key_rate * unchanged_key[[l, k]];
-                        prev_error[[i, l]] += query_rate * unchanged_query[[l, k]];
-                    }
-                }
-            }
+        grads.get(self.input_var, &self.tape).into_dimensionality::<Ix3>().unwrap()
+    }
+
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        let mut params = self.params.projections.parameters();
+        if let Some(relative_bias) = &self.params.relative_bias {
+            params.push(("relative_bias".to_string(), relative_bias));
         }
+        params
+    }
 
-        prev_error
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        let mut params = self.params.projections.parameters_mut();
+        if let Some(relative_bias) = &mut self.params.relative_bias {
+            params.push(("relative_bias".to_string(), relative_bias));
+        }
+        params
     }
-}
\ No newline at end of file
+}
@@ -1,38 +1,120 @@
-use ndarray::{Array1, Array2, arr1};
+use ndarray::{Array1, Array2, Array3, ArrayD, Axis, IxDyn, arr1};
 use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use crate::block::Block;
-use crate::dense::{Dense, inv_deriv_sigmoid};
+use crate::checkpoint::{Checkpoint, CheckpointError, TensorRecord, FORMAT_VERSION};
+use crate::dense::Dense;
 use crate::encoder_block::EncoderBlock;
-use crate::positional_encoder::PositionalEncoder;
+use crate::float::Float;
+use crate::initializer::Initializers;
+use crate::mask::padding_mask;
+use crate::parameter::{with_prefix, Parameter};
+use crate::positional_encoder::{PositionEncoding, PositionalEncoder};
+use crate::safetensors::{read_safetensors, write_safetensors};
+use crate::window::Window;
+
+/// The hyperparameters which determine the shape of a transformer
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransformerConfig {
+    pub num_words: usize,
+    pub dimensionality: usize,
+    pub num_encoders: usize,
+    pub num_heads: usize,
+    /// How many groups of attention heads share a key and value, one per head if unset
+    #[serde(default)]
+    pub num_kv_groups: Option<usize>,
+    pub layer_sizes: Vec<usize>,
+    #[serde(default)]
+    pub initializers: Initializers,
+    #[serde(default)]
+    pub positions: PositionEncoding,
+    /// Limits attention to a sliding window, so long reviews don't need memory for every pair of words
+    #[serde(default)]
+    pub window: Option<Window>,
+}
+
+impl Default for TransformerConfig {
+    /// The configuration of the example training in the README, sized for the
+    /// bundled 50-dimensional word embeddings
+    fn default() -> TransformerConfig {
+        TransformerConfig {
+            num_words: 12,
+            dimensionality: 50,
+            num_encoders: 2,
+            num_heads: 2,
+            num_kv_groups: None,
+            layer_sizes: vec![50, 400, 50],
+            initializers: Initializers::default(),
+            positions: PositionEncoding::default(),
+            window: None,
+        }
+    }
+}
+
+impl TransformerConfig {
+    /// Whether weights trained with one config mean the same in another. The
+    /// initializers, window and review length don't change what a weight does.
+    fn same_weights(&self, other: &TransformerConfig) -> bool {
+        self.dimensionality == other.dimensionality
+            && self.num_encoders == other.num_encoders
+            && self.num_heads == other.num_heads
+            && self.num_kv_groups.unwrap_or(self.num_heads) == other.num_kv_groups.unwrap_or(other.num_heads)
+            && self.layer_sizes == other.layer_sizes
+            && self.positions == other.positions
+    }
+}
+
+/// The attention weights of every head in every encoder block for a single review
+#[derive(Clone, Debug)]
+pub struct AttentionMaps<F: Float = f32> {
+    /// The words of the review, padding included, which label both axes of every map
+    pub words: Vec<String>,
+    /// One array per encoder block in the shape (heads, words, words), where row i
+    /// holds how much word i attends to each word. Rows and columns of padding are zero.
+    pub layers: Vec<Array3<F>>,
+}
 
 // Defines attention heads and dense layer.
-pub struct TransformerParams {
-    encoder_blocks: Array1::<EncoderBlock>,
+pub struct TransformerParams<F: Float = f32> {
+    encoder_blocks: Array1::<EncoderBlock<F>>,
 }
 
 // Defines multi-headed attention struct
-pub struct Transformer {
-    input: Array1::<String>,
-    output: f32,
-    num_words: usize,
+pub struct Transformer<F: Float = f32> {
+    input: Array2::<String>,
+    output: Array1::<F>,
+    padding: Array2<bool>,
+    config: TransformerConfig,
     dimensionality: usize,
-    pos_encoder: PositionalEncoder,
-    classifier: Dense,
+    pos_encoder: PositionalEncoder<F>,
+    classifier: Dense<F>,
     embedding: HashMap<String, Vec<f32>>,
-    params: TransformerParams,
+    params: TransformerParams<F>,
 }
 
-impl Transformer {
-    /// Create a new self-attention block with the given parameters
-    pub fn new(num_words: usize, dimensionality: usize, num_encoders: usize, num_heads: usize, layer_sizes: Array1<usize>, embedding: HashMap<String, Vec<f32>>) -> Transformer {
-        let encoder_blocks = Array1::from_shape_fn(num_encoders, |_| EncoderBlock::new(num_words, dimensionality, num_heads, layer_sizes.clone()));
+impl<F: Float> Transformer<F> {
+    /// Create a new transformer with the given parameters
+    pub fn new<R: Rng + ?Sized>(config: TransformerConfig, embedding: HashMap<String, Vec<f32>>, rng: &mut R) -> Transformer<F> {
+        let num_words = config.num_words;
+        let dimensionality = config.dimensionality;
+        let layer_sizes = Array1::from(config.layer_sizes.clone());
+        let num_kv_groups = config.num_kv_groups.unwrap_or(config.num_heads);
+        let encoder_blocks = Array1::from_shape_fn(config.num_encoders, |_| {
+            let mut block = EncoderBlock::new(num_words, dimensionality, config.num_heads, num_kv_groups, layer_sizes.clone(), config.positions, config.initializers, rng);
+            block.set_window(config.window.clone());
+            block
+        });
         let params = TransformerParams { encoder_blocks };
-        let pos_encoder = PositionalEncoder::new(num_words, dimensionality);
-        let classifier = Dense::new(arr1(&[num_words*dimensionality, 1]), false, true);
-        let block: Transformer = Transformer {
-            input: Array1::from_shape_fn(num_words, |_| "".to_string()),
-            output: 0.0,
-            num_words,
+        let pos_encoder = PositionalEncoder::new(num_words, dimensionality, config.positions);
+        // The classifier sees the mean of every word vector, so it works on reviews of any length
+        let classifier = Dense::new(arr1(&[dimensionality, 1]), false, true, config.initializers.classifier, config.initializers.biases, rng);
+        let block: Transformer<F> = Transformer {
+            input: Array2::from_shape_fn((1, num_words), |_| "".to_string()),
+            output: Array1::<F>::zeros(1),
+            padding: Array2::from_elem((1, num_words), true),
+            config,
             dimensionality,
             pos_encoder,
             classifier,
@@ -42,46 +124,226 @@ impl Transformer {
 
         block
     }
+
+    /// Returns the attention weights of every head in every encoder block for a
+    /// review, without storing anything
+    pub fn attention_maps(&self, review: &Array1<String>) -> AttentionMaps<F> {
+        let review = review.clone().insert_axis(Axis(0));
+        let padding = padding_mask(&review);
+
+        // Follow the same path as `predict`, keeping the weights of each encoder block
+        let mut enc_output = self.pos_encoder.predict(self.embed(&review));
+        let mut layers = vec![];
+        for encoder_block in self.params.encoder_blocks.iter() {
+            let (output, weights) = encoder_block.predict_with_attention(enc_output, Some(&padding));
+            enc_output = output;
+            layers.push(weights.index_axis_move(Axis(0), 0));
+        }
+
+        AttentionMaps { words: review.into_iter().collect(), layers }
+    }
+
+    /// Saves the hyperparameters and every weight of the transformer to a file
+    pub fn save(&self, path: &str) -> Result<(), CheckpointError> {
+        let tensors = self.parameters().into_iter().map(|(name, param)| TensorRecord {
+            name,
+            shape: param.shape().to_vec(),
+            data: param.value.iter().cloned().collect(),
+        }).collect();
+
+        let checkpoint = Checkpoint { version: FORMAT_VERSION, config: self.config.clone(), tensors };
+        checkpoint.write(path)
+    }
+
+    /// Loads a transformer saved by `save`, using the given word embeddings
+    pub fn load(path: &str, embedding: HashMap<String, Vec<f32>>) -> Result<Transformer<F>, CheckpointError> {
+        let checkpoint: Checkpoint<F> = Checkpoint::read(path)?;
+
+        // The embeddings must match the dimensionality the weights were trained with
+        for (word, vector) in embedding.iter() {
+            if vector.len() != checkpoint.config.dimensionality {
+                return Err(CheckpointError::EmbeddingMismatch { word: word.clone(), expected: checkpoint.config.dimensionality, found: vector.len() });
+            }
+        }
+
+        // Each word vector is split evenly between the attention heads
+        if checkpoint.config.num_heads == 0 || !checkpoint.config.dimensionality.is_multiple_of(checkpoint.config.num_heads) {
+            return Err(CheckpointError::Invalid(format!("dimensionality {} is not divisible by {} heads", checkpoint.config.dimensionality, checkpoint.config.num_heads)));
+        }
+
+        // Rotary positions rotate pairs of dimensions within each head
+        let head_size = checkpoint.config.dimensionality / checkpoint.config.num_heads;
+        if checkpoint.config.positions == PositionEncoding::Rotary && !head_size.is_multiple_of(2) {
+            return Err(CheckpointError::Invalid(format!("rotary positions need an even head size, not {}", head_size)));
+        }
+
+        // Relative biases split distances into earlier and later words, so need two buckets
+        if let PositionEncoding::RelativeBias { buckets, .. } = checkpoint.config.positions {
+            if buckets < 2 {
+                return Err(CheckpointError::Invalid(format!("relative biases need at least 2 buckets, not {}", buckets)));
+            }
+        }
+
+        // The heads are split evenly between the key/value groups
+        if let Some(groups) = checkpoint.config.num_kv_groups {
+            if groups == 0 || !checkpoint.config.num_heads.is_multiple_of(groups) {
+                return Err(CheckpointError::Invalid(format!("{} heads can't be split into {} key/value groups", checkpoint.config.num_heads, groups)));
+            }
+        }
+
+        // Each encoder's feed-forward layers map every word vector back to the dimensionality
+        let layer_sizes = &checkpoint.config.layer_sizes;
+        if layer_sizes.len() < 2 || layer_sizes[0] != checkpoint.config.dimensionality || layer_sizes[layer_sizes.len() - 1] != checkpoint.config.dimensionality {
+            return Err(CheckpointError::Invalid(format!("layer sizes {:?} must start and end with the dimensionality {}", layer_sizes, checkpoint.config.dimensionality)));
+        }
+
+        // The initialisation is overwritten, but must still be able to run
+        checkpoint.config.initializers.validate().map_err(CheckpointError::Invalid)?;
+
+        // Every weight is replaced by the saved one, so the initialisation doesn't matter
+        let mut transformer = Transformer::new(checkpoint.config, embedding, &mut StdRng::seed_from_u64(0));
+        let mut tensors = HashMap::new();
+        for tensor in checkpoint.tensors {
+            let value = ArrayD::from_shape_vec(IxDyn(&tensor.shape), tensor.data)
+                .map_err(|_| CheckpointError::Invalid(format!("tensor `{}` doesn't have {:?} values", tensor.name, tensor.shape)))?;
+            tensors.insert(tensor.name, value);
+        }
+        transformer.set_weights(tensors)?;
+
+        Ok(transformer)
+    }
+
+    /// Exports every weight of the transformer to a safetensors file, with the
+    /// hyperparameters stored as metadata
+    pub fn export_safetensors(&self, path: &str) -> Result<(), CheckpointError> {
+        let params = self.parameters();
+        let tensors: Vec<(String, &ArrayD<F>)> = params.iter().map(|(name, param)| (name.clone(), &param.value)).collect();
+
+        let mut metadata = HashMap::new();
+        metadata.insert("format_version".to_string(), FORMAT_VERSION.to_string());
+        metadata.insert("config".to_string(), serde_json::to_string(&self.config)?);
+        write_safetensors(path, &tensors, &metadata)
+    }
+
+    /// Replaces the weights of the transformer with those in a safetensors file.
+    /// The file must have been exported in this format version from a transformer
+    /// with the same shape and positions, and contain exactly its tensors.
+    pub fn import_safetensors(&mut self, path: &str) -> Result<(), CheckpointError> {
+        let (tensors, metadata) = read_safetensors(path)?;
+        let missing = |key: &str| CheckpointError::Invalid(format!("metadata has no `{}`", key));
+
+        let version = metadata.get("format_version").ok_or_else(|| missing("format_version"))?;
+        let version: u32 = version.parse().map_err(|_| CheckpointError::Invalid(format!("format version `{}` is not a number", version)))?;
+        if version != FORMAT_VERSION {
+            return Err(CheckpointError::Version { found: version, expected: FORMAT_VERSION });
+        }
+
+        let config: TransformerConfig = serde_json::from_str(metadata.get("config").ok_or_else(|| missing("config"))?)?;
+        if !config.same_weights(&self.config) {
+            return Err(CheckpointError::Invalid(format!("weights were exported from a transformer with {:?}, not {:?}", config, self.config)));
+        }
+
+        self.set_weights(tensors.into_iter().collect())
+    }
+
+    /// Looks up the embedding of every word in a batch of reviews, which can have
+    /// more or fewer words than `num_words`
+    fn embed(&self, reviews: &Array2<String>) -> Array3<F> {
+        let (batch_size, words) = reviews.dim();
+        Array3::<F>::from_shape_fn((batch_size, words, self.dimensionality), |(b, i, j)| F::cast(self.embedding[&reviews[[b, i]]][j] as f64))
+    }
+
+    /// Replaces every weight with the tensor of the same name, checking every
+    /// tensor is present with the right shape and that there are no others
+    fn set_weights(&mut self, mut tensors: HashMap<String, ArrayD<F>>) -> Result<(), CheckpointError> {
+        for (name, param) in self.parameters_mut() {
+            let tensor = tensors.remove(&name).ok_or_else(|| CheckpointError::MissingTensor(name.clone()))?;
+            if tensor.shape() != param.shape() {
+                return Err(CheckpointError::ShapeMismatch { name, expected: param.shape().to_vec(), found: tensor.shape().to_vec() });
+            }
+            param.value = tensor;
+        }
+
+        // Any tensors left over don't belong to a transformer with these hyperparameters
+        if let Some(name) = tensors.into_keys().next() {
+            return Err(CheckpointError::UnexpectedTensor(name));
+        }
+
+        Ok(())
+    }
 }
 
-impl Block for Transformer {
-    type Input = Array1<String>;
-    type Output = f32;
+/// Averages the word vectors of each review, leaving out the padded words
+fn mean_pool<F: Float>(value: &Array3<F>, padding: &Array2<bool>) -> Array2<F> {
+    let mut pooled = Array2::<F>::zeros((value.shape()[0], value.shape()[2]));
+    for (b, mut row) in pooled.outer_iter_mut().enumerate() {
+        let words = padding.row(b).iter().filter(|&&keep| keep).count().max(1);
+        for (i, word) in value.index_axis(Axis(0), b).outer_iter().enumerate() {
+            if padding[[b, i]] {
+                row.scaled_add(F::one() / F::cast(words as f64), &word);
+            }
+        }
+    }
+    pooled
+}
+
+/// Spreads the error of each review's mean evenly between its words, giving the padded words none
+fn mean_pool_error<F: Float>(error: &Array2<F>, padding: &Array2<bool>) -> Array3<F> {
+    let (batch_size, words) = padding.dim();
+    Array3::from_shape_fn((batch_size, words, error.shape()[1]), |(b, i, j)| {
+        let count = padding.row(b).iter().filter(|&&keep| keep).count().max(1);
+        if padding[[b, i]] { error[[b, j]] / F::cast(count as f64) } else { F::zero() }
+    })
+}
+
+impl<F: Float> Block<F> for Transformer<F> {
+    type Input = Array2<String>;
+    type Output = Array1<F>;
 
     fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
         self.input = value;
-    
-        // Convert input into embedded representation
-        let embedded = Array2::<f32>::from_shape_fn((self.num_words, self.dimensionality), |(i, j)| self.embedding[&self.input[i]][j]);
+
+        // Convert each review in the batch into its embedded representation
+        let embedded = self.embed(&self.input);
+        self.padding = padding_mask(&self.input);
     
         // Apply positional encoding to the embedded representation
         let mut enc_output = self.pos_encoder.forward_propagate(embedded);
 
-        // Iterate through each encoder block and forward propagate the output
+        // Iterate through each encoder block and forward propagate the output, ignoring padding
         for i in 0..self.params.encoder_blocks.len() {
-            enc_output = self.params.encoder_blocks[i].forward_propagate(enc_output);
+            enc_output = self.params.encoder_blocks[i].forward_propagate_masked(enc_output, Some(&self.padding));
         }
 
-        // Flatten the output for classification
-        let flat_output = enc_output.clone().into_shape(self.num_words*self.dimensionality).unwrap();
+        // Average the words of each review for classification, one row per review, without the padded words
+        let pooled = mean_pool(&enc_output, &self.padding);
     
-        // Forward propagate the flattened output through the classifier
-        self.output = self.classifier.forward_propagate(flat_output)[0];
+        // Forward propagate the pooled output through the classifier
+        let classified = self.classifier.forward_propagate(pooled);
+        self.output = classified.index_axis(Axis(1), 0).to_owned();
 
         // Return the output
-        self.output
+        self.output.clone()
+    }
+
+    /// Scores a batch of reviews without storing anything, so a trained
+    /// transformer wrapped in an `Arc` can be used from several threads
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        let padding = padding_mask(&value);
+        let mut enc_output = self.pos_encoder.predict(self.embed(&value));
+        for encoder_block in self.params.encoder_blocks.iter() {
+            enc_output = encoder_block.predict_masked(enc_output, Some(&padding));
+        }
+        self.classifier.predict(mean_pool(&enc_output, &padding)).index_axis(Axis(1), 0).to_owned()
     }
 
-    /// Rather than giving an error here, input a desired value.
+    /// The error is the derivative of the loss with respect to each prediction in the batch.
     fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
-        // Calculate the error of the last layer using the given error and the output of the neural network
-        let last_layer_error = 2.0 * (self.output - error) * inv_deriv_sigmoid(self.output);
-        
         // Back propagate the error to the classifier and get the classifier error
-        let classifier_error = self.classifier.back_propagate(arr1(&[last_layer_error]));
+        let classifier_error = self.classifier.back_propagate(error.insert_axis(Axis(1)));
         
-        // Reshape the classifier error to match the shape of the encoder error
-        let mut encoder_error = classifier_error.into_shape((self.num_words, self.dimensionality)).unwrap();
+        // Share the classifier error between the words that were averaged, which leaves none for padded words
+        let mut encoder_error = mean_pool_error(&classifier_error, &self.padding);
 
         // Iterate over the encoder blocks in reverse order and back propagate the encoder error
         for i in (0..self.params.encoder_blocks.len()).rev() {
@@ -91,6 +353,25 @@ impl Block for Transformer {
         // The positional encoder doesn't have any trainable parameters
         // self.pos_encoder.back_propagate(encoder_error);
 
-        arr1(&["".to_string()])
+        // The embeddings aren't trainable, so there is no error to return for the input
+        Array2::from_elem((0, 0), "".to_string())
+    }
+
+    fn parameters(&self) -> Vec<(String, &Parameter<F>)> {
+        let mut params = vec![];
+        for (i, encoder_block) in self.params.encoder_blocks.iter().enumerate() {
+            params.extend(with_prefix(&format!("encoder.{}", i), encoder_block.parameters()));
+        }
+        params.extend(with_prefix("classifier", self.classifier.parameters()));
+        params
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<F>)> {
+        let mut params = vec![];
+        for (i, encoder_block) in self.params.encoder_blocks.iter_mut().enumerate() {
+            params.extend(with_prefix(&format!("encoder.{}", i), encoder_block.parameters_mut()));
+        }
+        params.extend(with_prefix("classifier", self.classifier.parameters_mut()));
+        params
     }
 }
\ No newline at end of file
@@ -0,0 +1,102 @@
+use std::ops::Range;
+use ndarray::Array2;
+use serde::{Serialize, Deserialize};
+
+/// Restricts attention to a sliding window, so each word only attends to the words
+/// at most `size` positions away. Global words attend to and are attended to by
+/// every word, so information can still flow across the whole sequence.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    pub size: usize,
+    #[serde(default)]
+    pub global: Vec<usize>,
+}
+
+/// The keys each query attends to in sparse attention, stored query by query so
+/// only the pairs which are attended to take up any memory
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    first_query: usize,
+    num_keys: usize,
+    offsets: Vec<usize>,
+    keys: Vec<usize>,
+}
+
+impl Window {
+    /// Create a new window of `size` words either side of every word, plus the given global words
+    pub fn new(size: usize, global: Vec<usize>) -> Window {
+        Window { size, global }
+    }
+
+    /// Returns the keys, out of the first `keys` words, which each query at the
+    /// positions in `queries` attends to. A causal pattern leaves out every later word.
+    pub fn pattern(&self, queries: Range<usize>, keys: usize, causal: bool) -> Pattern {
+        let mut offsets = vec![0];
+        let mut indices = vec![];
+        for i in queries.clone() {
+            let last = if causal { (i + 1).min(keys) } else { keys };
+
+            // Global words see every key, the rest only their neighbours and the global words
+            let mut row: Vec<usize> = if self.global.contains(&i) {
+                (0..last).collect()
+            } else {
+                let neighbours = i.saturating_sub(self.size)..(i + self.size + 1).min(last);
+                neighbours.chain(self.global.iter().copied().filter(|&j| j < last)).collect()
+            };
+            row.sort_unstable();
+            row.dedup();
+
+            indices.extend(row);
+            offsets.push(indices.len());
+        }
+
+        Pattern { first_query: queries.start, num_keys: keys, offsets, keys: indices }
+    }
+}
+
+impl Pattern {
+    /// Returns the number of queries
+    pub fn queries(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Returns the number of words which can be attended to
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Returns the number of (query, key) pairs attended to
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if no query attends to any key
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the range of pairs belonging to the query in the given row
+    pub fn row(&self, query: usize) -> Range<usize> {
+        self.offsets[query]..self.offsets[query + 1]
+    }
+
+    /// Returns the key of the given pair
+    pub fn key(&self, pair: usize) -> usize {
+        self.keys[pair]
+    }
+
+    /// Returns the positions of the query and key of every pair, in order
+    pub fn pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.queries()).flat_map(move |i| self.row(i).map(move |pair| (self.first_query + i, self.keys[pair])))
+    }
+
+    /// Marks the pairs in which neither word is padding, one row per example, when
+    /// the queries and keys are both the words of the padded sequences
+    pub fn padding_mask(&self, padding: &Array2<bool>) -> Array2<bool> {
+        let pairs: Vec<(usize, usize)> = self.pairs().collect();
+        Array2::from_shape_fn((padding.nrows(), self.len()), |(b, pair)| {
+            let (i, j) = pairs[pair];
+            padding[[b, i]] && padding[[b, j]]
+        })
+    }
+}
\ No newline at end of file
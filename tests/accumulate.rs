@@ -0,0 +1,46 @@
+mod common;
+
+use ndarray::{arr1, ArrayD};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::transformer::Transformer;
+use common::{config, reviews, small_model};
+
+fn transformer() -> Transformer<f64> {
+    small_model(config(), &mut StdRng::seed_from_u64(0))
+}
+
+/// Runs one forward and backward pass over a single review, without clearing the gradients
+fn accumulate(transformer: &mut Transformer<f64>, offset: usize) {
+    transformer.forward_propagate(reviews(1, offset));
+    transformer.back_propagate(arr1(&[1.0]));
+}
+
+/// Returns the gradient of every parameter of a fresh transformer after one review
+fn single_gradients(offset: usize) -> Vec<ArrayD<f64>> {
+    let mut transformer = transformer();
+    accumulate(&mut transformer, offset);
+    transformer.parameters().into_iter().map(|(_, param)| param.grad.clone()).collect()
+}
+
+#[test]
+fn gradients_sum_across_examples_until_zeroed() {
+    let (first, second) = (single_gradients(0), single_gradients(1));
+    assert!(first.iter().chain(second.iter()).any(|grad| grad.iter().any(|&x| x != 0.0)));
+
+    let mut transformer = transformer();
+    accumulate(&mut transformer, 0);
+    accumulate(&mut transformer, 1);
+    for (((name, param), a), b) in transformer.parameters().into_iter().zip(first.iter()).zip(second.iter()) {
+        let sum = a + b;
+        for (x, y) in param.grad.iter().zip(sum.iter()) {
+            assert!((x - y).abs() < 1e-12, "{}: {} isn't the sum {}", name, x, y);
+        }
+    }
+
+    transformer.zero_grad();
+    for (name, param) in transformer.parameters() {
+        assert!(param.grad.iter().all(|&x| x == 0.0), "{} wasn't reset", name);
+    }
+}
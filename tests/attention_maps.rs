@@ -0,0 +1,40 @@
+mod common;
+
+use ndarray::{arr1, s, Array1, Axis};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::transformer::{Transformer, TransformerConfig};
+use common::{config, small_model};
+
+fn transformer() -> Transformer {
+    let config = TransformerConfig { num_words: 4, num_encoders: 3, layer_sizes: vec![4, 8, 4], ..config() };
+    small_model(config, &mut StdRng::seed_from_u64(0))
+}
+
+#[test]
+fn maps_cover_every_layer_and_head() {
+    let review: Array1<String> = arr1(&["good", "bad", "film", ""]).mapv(|word| word.to_string());
+    let maps = transformer().attention_maps(&review);
+
+    assert_eq!(maps.words, vec!["good", "bad", "film", ""]);
+    assert_eq!(maps.layers.len(), 3);
+    for layer in maps.layers.iter() {
+        assert_eq!(layer.shape(), &[2, 4, 4]);
+        for head in layer.axis_iter(Axis(0)) {
+            // Each real word's weights sum to one and none of them go to the padding
+            for row in head.slice(s![..3, ..]).rows() {
+                assert!((row.sum() - 1.0).abs() < 1e-5, "weights sum to {}", row.sum());
+                assert_eq!(row[3], 0.0);
+            }
+            assert!(head.row(3).iter().all(|&weight| weight == 0.0));
+        }
+    }
+}
+
+#[test]
+fn maps_change_with_the_review() {
+    let transformer = transformer();
+    let first = transformer.attention_maps(&arr1(&["good", "bad", "film", "film"]).mapv(|word| word.to_string()));
+    let second = transformer.attention_maps(&arr1(&["bad", "good", "film", "film"]).mapv(|word| word.to_string()));
+    assert_ne!(first.layers[0], second.layers[0]);
+}
\ No newline at end of file
@@ -0,0 +1,98 @@
+use ndarray::{arr1, arr2, Array2, Array3, ArrayD, Axis};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rusttransformer::add_and_norm::AddAndNorm;
+use rusttransformer::autograd::Tape;
+use rusttransformer::block::Block;
+
+fn assert_close(found: &ArrayD<f64>, expected: ArrayD<f64>) {
+    assert_eq!(found.shape(), expected.shape());
+    for (f, e) in found.iter().zip(expected.iter()) {
+        assert!((f - e).abs() < 1e-9, "expected {} but found {}", expected, found);
+    }
+}
+
+#[test]
+fn broadcast_gradients_are_summed_back_to_each_operand() {
+    let mut tape = Tape::<f64>::new();
+    let a = tape.leaf(arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]).into_dyn());
+    let row = tape.leaf(arr1(&[1.0, 10.0, 100.0]).into_dyn());
+    let column = tape.leaf(arr2(&[[2.0], [3.0]]).into_dyn());
+    let sum = tape.add(a, row);
+    let output = tape.mul(sum, column);
+
+    let grads = tape.backward(output, ArrayD::ones(vec![2, 3]));
+    assert_close(&grads.get(a, &tape), arr2(&[[2.0, 2.0, 2.0], [3.0, 3.0, 3.0]]).into_dyn());
+    assert_close(&grads.get(row, &tape), arr1(&[5.0, 5.0, 5.0]).into_dyn());
+    assert_close(&grads.get(column, &tape), arr2(&[[117.0], [126.0]]).into_dyn());
+}
+
+#[test]
+fn mean_and_div_gradients() {
+    let mut tape = Tape::<f64>::new();
+    let a = tape.leaf(arr2(&[[1.0, 2.0], [3.0, 5.0]]).into_dyn());
+    let b = tape.leaf(arr2(&[[2.0, 4.0], [1.0, 10.0]]).into_dyn());
+    let quotient = tape.div(a, b);
+    let output = tape.mean(quotient);
+    assert_close(tape.value(output), arr2(&[[0.5], [1.75]]).into_dyn());
+
+    // d/da = 1/(n·b) and d/db = -a/(n·b²), with n = 2 words in the mean
+    let grads = tape.backward(output, ArrayD::ones(vec![2, 1]));
+    assert_close(&grads.get(a, &tape), arr2(&[[0.25, 0.125], [0.5, 0.05]]).into_dyn());
+    assert_close(&grads.get(b, &tape), arr2(&[[-0.125, -0.0625], [-1.5, -0.025]]).into_dyn());
+}
+
+#[test]
+fn slice_and_concatenate_route_gradients_to_their_sources() {
+    let mut tape = Tape::<f64>::new();
+    let a = tape.leaf(arr2(&[[1.0, 2.0, 3.0, 4.0]]).into_dyn());
+    let b = tape.leaf(arr2(&[[5.0]]).into_dyn());
+
+    // Swap the halves of `a`, dropping its third element, and append `b`
+    let first = tape.slice(a, 0..2);
+    let last = tape.slice(a, 3..4);
+    let output = tape.concatenate(&[last, first, b]);
+    assert_close(tape.value(output), arr2(&[[4.0, 1.0, 2.0, 5.0]]).into_dyn());
+
+    let grads = tape.backward(output, arr2(&[[10.0, 20.0, 30.0, 40.0]]).into_dyn());
+    assert_close(&grads.get(a, &tape), arr2(&[[20.0, 30.0, 0.0, 10.0]]).into_dyn());
+    assert_close(&grads.get(b, &tape), arr2(&[[40.0]]).into_dyn());
+}
+
+/// The layer norm Jacobian which `AddAndNorm::back_propagate` computed by hand before the tape,
+/// with the same epsilon added to the variance
+fn hand_written_norm_error(input: &Array2<f64>, error: &Array2<f64>) -> Array2<f64> {
+    let mut prev_error = Array2::<f64>::zeros(input.raw_dim());
+    for (count, x) in input.axis_iter(Axis(0)).enumerate() {
+        let n = x.len() as f64;
+        let mean = x.mean().unwrap();
+        let stdev = (x.var(0.0) + 1e-5).sqrt();
+        let x_matrix = Array2::from_shape_fn((x.len(), x.len()), |(i, j)| (x[i] - mean) * (x[j] - mean));
+        let jacobian = ((Array2::<f64>::eye(x.len()) * n) - 1.0) / (n * stdev) - (x_matrix / (n * stdev.powi(3)));
+        prev_error.row_mut(count).assign(&error.row(count).dot(&jacobian));
+    }
+    prev_error
+}
+
+#[test]
+fn add_and_norm_matches_the_hand_written_gradient() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let original = Array3::from_shape_simple_fn((2, 4, 6), || rng.gen_range(-1.0..1.0));
+    let modified = Array3::from_shape_simple_fn((2, 4, 6), || rng.gen_range(-1.0..1.0));
+    let error = Array3::from_shape_simple_fn((2, 4, 6), || rng.gen_range(-1.0..1.0));
+
+    let mut norm = AddAndNorm::<f64>::new();
+    norm.forward_propagate((original.clone(), modified.clone()));
+    let (original_error, modified_error) = norm.back_propagate(error.clone());
+
+    // Each example of the batch is normalised separately
+    let sum = &original + &modified;
+    for b in 0..2 {
+        let expected = hand_written_norm_error(&sum.index_axis(Axis(0), b).to_owned(), &error.index_axis(Axis(0), b).to_owned());
+        for found in [&original_error, &modified_error] {
+            for (f, e) in found.index_axis(Axis(0), b).iter().zip(expected.iter()) {
+                assert!((f - e).abs() < 1e-9, "expected {} but found {}", e, f);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,49 @@
+mod common;
+
+use ndarray::{arr1, arr2, Array1, ArrayD, Axis};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::dataset::PAD;
+use rusttransformer::loss::mean_squared_error_derivative;
+use rusttransformer::transformer::Transformer;
+use common::{config, small_model};
+
+fn transformer() -> Transformer<f64> {
+    small_model(config(), &mut StdRng::seed_from_u64(0))
+}
+
+/// Returns the gradient of every parameter
+fn gradients(transformer: &Transformer<f64>) -> Vec<ArrayD<f64>> {
+    transformer.parameters().into_iter().map(|(_, param)| param.grad.clone()).collect()
+}
+
+#[test]
+fn batches_match_single_reviews() {
+    let reviews = arr2(&[["good", "film", PAD], ["bad", PAD, PAD], ["film", "good", "bad"]]).mapv(|word| word.to_string());
+    let targets = arr1(&[1.0, 0.0, 1.0]);
+
+    let mut batched = transformer();
+    let predictions = batched.forward_propagate(reviews.clone());
+    batched.back_propagate(mean_squared_error_derivative(&predictions, &targets));
+
+    // Each review on its own, with the loss of a batch of one
+    let mut mean_gradients: Vec<ArrayD<f64>> = gradients(&batched).iter().map(|grad| ArrayD::zeros(grad.raw_dim())).collect();
+    for (b, review) in reviews.outer_iter().enumerate() {
+        let mut single = transformer();
+        let prediction = single.forward_propagate(review.to_owned().insert_axis(Axis(0)));
+        assert!((prediction[0] - predictions[b]).abs() < 1e-12, "review {} scores {} alone but {} in the batch", b, prediction[0], predictions[b]);
+
+        single.back_propagate(mean_squared_error_derivative(&prediction, &Array1::from_elem(1, targets[b])));
+        for (mean, grad) in mean_gradients.iter_mut().zip(gradients(&single)) {
+            *mean += &(grad / reviews.nrows() as f64);
+        }
+    }
+
+    // The batch's loss is the mean of the reviews' losses, so its gradients are too
+    for ((name, param), mean) in batched.parameters().into_iter().zip(mean_gradients.iter()) {
+        for (x, y) in param.grad.iter().zip(mean.iter()) {
+            assert!((x - y).abs() < 1e-12, "{}: batch gradient {} isn't the mean {}", name, x, y);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use ndarray::{s, Array3};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::grad_check::check_gradients;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+use common::{assert_close, random, EPSILON, TOLERANCE};
+
+const WORDS: usize = 5;
+const DIMENSIONALITY: usize = 4;
+
+/// Checks that changing the words after position i never changes the output at
+/// or before it, and that an error at position i never reaches a later word
+fn assert_causal<B>(block: &mut B, rng: &mut StdRng)
+where
+    B: Block<f64, Input = Array3<f64>, Output = Array3<f64>>,
+{
+    let input = random((2, WORDS, DIMENSIONALITY), rng);
+    let output = block.predict(input.clone());
+
+    for i in 0..WORDS {
+        let mut changed = input.clone();
+        changed.slice_mut(s![.., i + 1.., ..]).assign(&random((2, WORDS - i - 1, DIMENSIONALITY), rng));
+        let changed_output = block.predict(changed);
+        assert_eq!(output.slice(s![.., ..=i, ..]), changed_output.slice(s![.., ..=i, ..]), "position {} depends on a later word", i);
+
+        block.forward_propagate(input.clone());
+        let mut error = Array3::zeros((2, WORDS, DIMENSIONALITY));
+        error.slice_mut(s![.., i, ..]).assign(&random((2, DIMENSIONALITY), rng));
+        let input_grad = block.back_propagate(error);
+        block.zero_grad();
+        assert!(input_grad.slice(s![.., i + 1.., ..]).iter().all(|&x| x == 0.0), "position {} sends gradient to a later word", i);
+    }
+}
+
+fn assert_gradients_close<B>(block: &mut B, rng: &mut StdRng)
+where
+    B: Block<f64, Input = Array3<f64>, Output = Array3<f64>>,
+{
+    let input = random((2, WORDS, DIMENSIONALITY), rng);
+    assert_close(check_gradients(block, input, EPSILON, rng), TOLERANCE);
+}
+
+#[test]
+fn causal_self_attention() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut attention = SelfAttention::<f64>::new(WORDS, DIMENSIONALITY, DIMENSIONALITY, 1, true, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    assert_causal(&mut attention, &mut rng);
+    assert_gradients_close(&mut attention, &mut rng);
+}
+
+#[test]
+fn causal_multi_headed_attention() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut attention = MultiHeadedAttention::<f64>::new(2, 2, WORDS, DIMENSIONALITY, true, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    assert_causal(&mut attention, &mut rng);
+    assert_gradients_close(&mut attention, &mut rng);
+}
+
+#[test]
+fn bidirectional_attention_sees_later_words() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let attention = SelfAttention::<f64>::new(WORDS, DIMENSIONALITY, DIMENSIONALITY, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    let input = random((1, WORDS, DIMENSIONALITY), &mut rng);
+    let mut changed = input.clone();
+    changed.slice_mut(s![.., WORDS - 1, ..]).fill(2.0);
+    assert_ne!(attention.predict(input).slice(s![.., 0, ..]), attention.predict(changed).slice(s![.., 0, ..]));
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+mod common;
+
+use serde_json::Value;
+use std::fs;
+use rusttransformer::block::Block;
+use rusttransformer::checkpoint::{CheckpointError, FORMAT_VERSION};
+use rusttransformer::transformer::Transformer;
+use common::{config, reviews, seeded_transformer, temp_path, words};
+
+/// Saves a fresh transformer, lets `edit` change the saved JSON, then loads it back
+fn load_edited(name: &str, edit: impl FnOnce(&mut Value)) -> Result<Transformer, CheckpointError> {
+    let path = temp_path(name, "json");
+    let transformer: Transformer = seeded_transformer(config(), 1);
+    transformer.save(&path).unwrap();
+    let mut json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    edit(&mut json);
+    fs::write(&path, json.to_string()).unwrap();
+    let loaded = Transformer::load(&path, words());
+    fs::remove_file(&path).unwrap();
+    loaded
+}
+
+#[test]
+fn saved_transformer_loads_identically() {
+    let path = temp_path("round_trip", "json");
+    let transformer: Transformer = seeded_transformer(config(), 1);
+    transformer.save(&path).unwrap();
+    let loaded: Transformer = Transformer::load(&path, words()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    for ((name, saved), (loaded_name, loaded)) in transformer.parameters().iter().zip(loaded.parameters().iter()) {
+        assert_eq!(name, loaded_name);
+        assert_eq!(saved.value, loaded.value, "{} changed", name);
+    }
+    assert_eq!(transformer.predict(reviews(2, 0)), loaded.predict(reviews(2, 0)));
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let result = load_edited("version", |json| json["version"] = Value::from(FORMAT_VERSION - 1));
+    assert!(matches!(result, Err(CheckpointError::Version { found, expected }) if found == FORMAT_VERSION - 1 && expected == FORMAT_VERSION));
+}
+
+#[test]
+fn missing_tensors_are_rejected() {
+    let result = load_edited("missing", |json| {
+        json["tensors"].as_array_mut().unwrap().retain(|tensor| tensor["name"] != "classifier.layer.0.weights");
+    });
+    assert!(matches!(result, Err(CheckpointError::MissingTensor(name)) if name == "classifier.layer.0.weights"));
+}
+
+#[test]
+fn unexpected_tensors_are_rejected() {
+    let result = load_edited("unexpected", |json| {
+        let tensors = json["tensors"].as_array_mut().unwrap();
+        let mut extra = tensors[0].clone();
+        extra["name"] = Value::from("encoder.9.extra");
+        tensors.push(extra);
+    });
+    assert!(matches!(result, Err(CheckpointError::UnexpectedTensor(name)) if name == "encoder.9.extra"));
+}
+
+#[test]
+fn mismatched_shapes_are_rejected() {
+    let result = load_edited("shape", |json| {
+        let tensor = json["tensors"].as_array_mut().unwrap().iter_mut().find(|tensor| tensor["name"] == "classifier.layer.0.weights").unwrap();
+        tensor["shape"] = serde_json::json!([1, 4]);
+    });
+    assert!(matches!(result, Err(CheckpointError::ShapeMismatch { name, expected, found }) if name == "classifier.layer.0.weights" && expected == vec![4, 1] && found == vec![1, 4]));
+}
+
+#[test]
+fn invalid_layer_sizes_are_rejected() {
+    for layer_sizes in [vec![], vec![4], vec![12, 8, 12]] {
+        let result = load_edited("layer_sizes", |json| json["config"]["layer_sizes"] = Value::from(layer_sizes.clone()));
+        assert!(matches!(result, Err(CheckpointError::Invalid(_))), "{:?} was accepted", layer_sizes);
+    }
+}
+
+#[test]
+fn rotary_positions_with_odd_head_sizes_are_rejected() {
+    let result = load_edited("rotary", |json| {
+        json["config"]["num_heads"] = Value::from(4);
+        json["config"]["positions"] = Value::from("Rotary");
+    });
+    assert!(matches!(result, Err(CheckpointError::Invalid(reason)) if reason.contains("even head size")));
+}
+
+#[test]
+fn relative_biases_with_one_bucket_are_rejected() {
+    let result = load_edited("buckets", |json| json["config"]["positions"] = serde_json::json!({ "RelativeBias": { "buckets": 1, "max_distance": 4 } }));
+    assert!(matches!(result, Err(CheckpointError::Invalid(reason)) if reason.contains("2 buckets")));
+}
+
+#[test]
+fn other_versions_are_rejected_before_their_body_is_read() {
+    // A future format might lay out its tensors differently
+    let result = load_edited("future", |json| {
+        json["version"] = Value::from(FORMAT_VERSION + 1);
+        json["tensors"] = serde_json::json!({ "layout": "unknown" });
+    });
+    assert!(matches!(result, Err(CheckpointError::Version { found, expected }) if found == FORMAT_VERSION + 1 && expected == FORMAT_VERSION));
+}
+
+#[test]
+fn invalid_initializers_are_rejected() {
+    for std in [-1.0, 0.0] {
+        let result = load_edited("initializers", |json| json["config"]["initializers"]["attention"] = serde_json::json!({ "TruncatedNormal": { "std": std } }));
+        assert!(matches!(result, Err(CheckpointError::Invalid(reason)) if reason.contains("standard deviation")), "{} was accepted", std);
+    }
+}
@@ -0,0 +1,94 @@
+// Fixtures shared by the integration tests. Not every test uses every fixture.
+#![allow(dead_code)]
+
+use ndarray::{concatenate, s, Array, Array2, Array3, Axis, Dimension, ShapeBuilder};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use rusttransformer::float::Float;
+use rusttransformer::grad_check::TensorError;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::transformer::{Transformer, TransformerConfig};
+
+// Central differences in f64 are accurate to well below a millionth, so even
+// a slightly wrong gradient stands out
+pub const EPSILON: f64 = 1e-5;
+pub const TOLERANCE: f64 = 1e-6;
+
+/// The words the fixture models know, starting with the padding word
+pub const WORDS: [&str; 4] = ["", "good", "bad", "film"];
+
+/// Returns a random four-dimensional embedding of every word in `WORDS`
+pub fn embedding(rng: &mut StdRng) -> HashMap<String, Vec<f32>> {
+    WORDS.iter()
+        .map(|word| (word.to_string(), (0..4).map(|_| rng.gen_range(-1.0..1.0)).collect()))
+        .collect()
+}
+
+/// Returns an array of the given shape filled with values uniform on ±1
+pub fn random<F: Float, Sh: ShapeBuilder>(shape: Sh, rng: &mut StdRng) -> Array<F, Sh::Dim> where Sh::Dim: Dimension {
+    Array::from_shape_simple_fn(shape, || F::cast(rng.gen_range(-1.0..1.0)))
+}
+
+/// Checks the gradient checker compared some tensors and found them all within `tolerance`
+pub fn assert_close(errors: Vec<TensorError<f64>>, tolerance: f64) {
+    assert!(!errors.is_empty());
+    for error in errors {
+        assert!(error.relative_error < tolerance, "{} has relative error {}", error.name, error.relative_error);
+    }
+}
+
+/// Returns the config of a small transformer over the fixture embedding
+pub fn config() -> TransformerConfig {
+    TransformerConfig { num_words: 3, dimensionality: 4, num_encoders: 2, num_heads: 2, layer_sizes: vec![4, 8, 4], ..TransformerConfig::default() }
+}
+
+/// Builds a transformer with the given config, drawing its embedding and then its weights from `rng`
+pub fn small_model<F: Float>(config: TransformerConfig, rng: &mut StdRng) -> Transformer<F> {
+    let embedding = embedding(rng);
+    Transformer::new(config, embedding, rng)
+}
+
+/// Returns the embedding of `WORDS` shared by every transformer built by `seeded_transformer`,
+/// so one saved with it can be loaded with it again
+pub fn words() -> HashMap<String, Vec<f32>> {
+    embedding(&mut StdRng::seed_from_u64(0))
+}
+
+/// Builds a transformer over `words()` with weights drawn from the given seed, so
+/// transformers of the same config differ only in their weights
+pub fn seeded_transformer<F: Float>(config: TransformerConfig, seed: u64) -> Transformer<F> {
+    Transformer::new(config, words(), &mut StdRng::seed_from_u64(seed))
+}
+
+/// Returns a path in the temporary directory which no other test uses, as long
+/// as every test passes a different name and extension
+pub fn temp_path(name: &str, extension: &str) -> String {
+    std::env::temp_dir().join(format!("rusttransformer_{}_{}.{}", name, std::process::id(), extension)).to_str().unwrap().to_string()
+}
+
+/// Returns `batch_size` reviews of `config().num_words` words, each starting one
+/// word later in `WORDS` than the last and the first starting `offset` words in
+pub fn reviews(batch_size: usize, offset: usize) -> Array2<String> {
+    Array2::from_shape_fn((batch_size, config().num_words), |(b, i)| WORDS[(offset + b + i) % WORDS.len()].to_string())
+}
+
+/// Returns one of each position encoding
+pub fn positions() -> [PositionEncoding; 4] {
+    [PositionEncoding::Sinusoidal, PositionEncoding::Rotary, PositionEncoding::Alibi, PositionEncoding::RelativeBias { buckets: 6, max_distance: 4 }]
+}
+
+/// Feeds the sequence through `step` one word at a time, collecting the outputs
+pub fn decode(input: &Array3<f64>, mut step: impl FnMut(Array3<f64>) -> Array3<f64>) -> Array3<f64> {
+    let outputs: Vec<Array3<f64>> = (0..input.shape()[1]).map(|i| step(input.slice(s![.., i..i + 1, ..]).to_owned())).collect();
+    let views: Vec<_> = outputs.iter().map(|output| output.view()).collect();
+    concatenate(Axis(1), &views).unwrap()
+}
+
+/// Checks that two outputs only differ by rounding
+pub fn assert_same(a: &Array3<f64>, b: &Array3<f64>, positions: PositionEncoding) {
+    assert_eq!(a.shape(), b.shape());
+    for (x, y) in a.iter().zip(b.iter()) {
+        assert!((x - y).abs() < 1e-12, "{:?}: {} differs from {}", positions, x, y);
+    }
+}
\ No newline at end of file
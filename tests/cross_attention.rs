@@ -0,0 +1,64 @@
+mod common;
+
+use ndarray::{s, Array2, Array3};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::cross_attention::CrossAttention;
+use rusttransformer::grad_check::check_gradients;
+use rusttransformer::initializer::Initializer;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+use common::{assert_close, random, EPSILON, TOLERANCE};
+
+#[test]
+fn gradients_reach_both_sequences() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut attention = CrossAttention::<f64>::new(3, 5, 4, 2, Initializer::XavierUniform, &mut rng);
+    let input: (Array3<f64>, Array3<f64>) = (random((2, 3, 4), &mut rng), random((2, 5, 4), &mut rng));
+    assert_close(check_gradients(&mut attention, input.clone(), EPSILON, &mut rng), TOLERANCE);
+
+    attention.forward_propagate(input);
+    let (sequence_error, context_error) = attention.back_propagate(random((2, 3, 2), &mut rng));
+    assert_eq!(sequence_error.shape(), &[2, 3, 4]);
+    assert_eq!(context_error.shape(), &[2, 5, 4]);
+    assert!(context_error.iter().any(|&x| x != 0.0));
+}
+
+#[test]
+fn attending_to_itself_matches_self_attention() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut cross = CrossAttention::<f64>::new(4, 4, 4, 4, Initializer::XavierUniform, &mut rng);
+    let mut own = SelfAttention::<f64>::new(4, 4, 4, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    for ((_, from), (_, to)) in cross.parameters().into_iter().zip(own.parameters_mut()) {
+        to.value = from.value.clone();
+    }
+
+    let input = random((2, 4, 4), &mut rng);
+    let expected = own.forward_propagate(input.clone());
+    let output = cross.forward_propagate((input.clone(), input));
+    assert!((&output - &expected).iter().all(|x| x.abs() < 1e-12));
+
+    // The error of a shared input is the sum of both of its roles
+    let error = random((2, 4, 4), &mut rng);
+    let expected = own.back_propagate(error.clone());
+    let (sequence_error, context_error) = cross.back_propagate(error);
+    assert!((&(&sequence_error + &context_error) - &expected).iter().all(|x| x.abs() < 1e-12));
+}
+
+#[test]
+fn padded_context_is_ignored() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let attention = CrossAttention::<f64>::new(3, 5, 4, 4, Initializer::XavierUniform, &mut rng);
+    let sequence_padding = Array2::from_elem((1, 3), true);
+    let context_padding = Array2::from_shape_fn((1, 5), |(_, j)| j < 3);
+
+    let sequence = random((1, 3, 4), &mut rng);
+    let context = random((1, 5, 4), &mut rng);
+    let mut changed = context.clone();
+    changed.slice_mut(s![.., 3.., ..]).fill(5.0);
+
+    let output = attention.predict_masked((sequence.clone(), context), Some((&sequence_padding, &context_padding)));
+    let changed_output = attention.predict_masked((sequence, changed), Some((&sequence_padding, &context_padding)));
+    assert_eq!(output, changed_output);
+}
\ No newline at end of file
@@ -0,0 +1,51 @@
+mod common;
+
+use ndarray::{arr1, Array1};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::float::Float;
+use rusttransformer::loss::{mean_squared_error, mean_squared_error_derivative};
+use rusttransformer::optimizer::{Optimizer, Sgd};
+use rusttransformer::transformer::Transformer;
+use common::{config, reviews, small_model};
+
+#[test]
+fn transformer_predictions_agree_across_precisions() {
+    // The same seed gives the same weights, rounded to each precision
+    let mut single: Transformer<f32> = small_model(config(), &mut StdRng::seed_from_u64(6));
+    let mut double: Transformer<f64> = small_model(config(), &mut StdRng::seed_from_u64(6));
+
+    let single_predictions = single.forward_propagate(reviews(2, 0));
+    let double_predictions = double.forward_propagate(reviews(2, 0));
+    for (s, d) in single_predictions.iter().zip(double_predictions.iter()) {
+        assert!((*s as f64 - d).abs() < 1e-4, "f32 predicted {} but f64 predicted {}", s, d);
+    }
+}
+
+fn training_reduces_loss<F: Float>() {
+    let mut transformer: Transformer<F> = small_model(config(), &mut StdRng::seed_from_u64(8));
+    let mut optimizer: Sgd<F> = Sgd::new(0.1);
+    let targets: Array1<F> = arr1(&[F::one(), F::zero()]);
+
+    let initial = mean_squared_error(&transformer.forward_propagate(reviews(2, 0)), &targets);
+    for _ in 0..20 {
+        let predictions = transformer.forward_propagate(reviews(2, 0));
+        transformer.back_propagate(mean_squared_error_derivative(&predictions, &targets));
+        optimizer.step(&mut transformer);
+        transformer.zero_grad();
+    }
+    let trained = mean_squared_error(&transformer.forward_propagate(reviews(2, 0)), &targets);
+
+    assert!(trained < initial, "loss went from {} to {}", initial, trained);
+}
+
+#[test]
+fn training_reduces_loss_f32() {
+    training_reduces_loss::<f32>();
+}
+
+#[test]
+fn training_reduces_loss_f64() {
+    training_reduces_loss::<f64>();
+}
\ No newline at end of file
@@ -0,0 +1,82 @@
+mod common;
+
+use ndarray::{arr1, Array3};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::add_and_norm::AddAndNorm;
+use rusttransformer::dense::Dense;
+use rusttransformer::encoder_block::EncoderBlock;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::grad_check::{check_gradients, check_parameters};
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+use rusttransformer::transformer::Transformer;
+use common::{assert_close, config, random, reviews, small_model, EPSILON, TOLERANCE};
+
+#[test]
+fn dense_linear() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut dense = Dense::<f64>::new(arr1(&[5, 4, 3]), true, false, Initializer::XavierUniform, Initializer::Zeros, &mut rng);
+    let input = random((2, 5), &mut rng);
+    assert_close(check_gradients(&mut dense, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn dense_relu() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut dense = Dense::<f64>::new(arr1(&[5, 4, 3]), false, false, Initializer::HeNormal, Initializer::HeNormal, &mut rng);
+    let input = random((2, 5), &mut rng);
+    assert_close(check_gradients(&mut dense, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn dense_sigmoid() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut dense = Dense::<f64>::new(arr1(&[5, 4, 1]), false, true, Initializer::XavierNormal, Initializer::TruncatedNormal { std: 0.1 }, &mut rng);
+    let input = random((2, 5), &mut rng);
+    assert_close(check_gradients(&mut dense, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn self_attention() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut attention = SelfAttention::<f64>::new(3, 4, 4, 1, false, PositionEncoding::Sinusoidal, Initializer::Orthogonal, &mut rng);
+    let input = random((2, 3, 4), &mut rng);
+    assert_close(check_gradients(&mut attention, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn add_and_norm() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let mut norm = AddAndNorm::<f64>::new();
+    let input: (Array3<f64>, Array3<f64>) = (random((2, 3, 4), &mut rng), random((2, 3, 4), &mut rng));
+    assert_close(check_gradients(&mut norm, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn multi_headed_attention() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut attention = MultiHeadedAttention::<f64>::new(2, 2, 3, 4, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    let input = random((2, 3, 4), &mut rng);
+    assert_close(check_gradients(&mut attention, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn encoder_block() {
+    let mut rng = StdRng::seed_from_u64(6);
+    // With zero biases, a word whose hidden layer is all zero would put the output exactly on
+    // the ReLU kink, where finite differences don't match the gradient. Random biases keep
+    // every pre-activation away from it, whatever the seed.
+    let init = Initializers { biases: Initializer::HeNormal, ..Initializers::default() };
+    let mut encoder = EncoderBlock::<f64>::new(3, 4, 2, 2, arr1(&[4, 8, 4]), PositionEncoding::Sinusoidal, init, &mut rng);
+    let input = random((2, 3, 4), &mut rng);
+    assert_close(check_gradients(&mut encoder, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn transformer() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut transformer: Transformer<f64> = small_model(config(), &mut rng);
+    assert_close(check_parameters(&mut transformer, reviews(2, 0), EPSILON, &mut rng), TOLERANCE);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use ndarray::{Array2, Axis};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+use common::random;
+
+#[test]
+fn heads_split_the_dimensionality() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let attention: MultiHeadedAttention = MultiHeadedAttention::new(4, 4, 3, 8, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    for (name, param) in attention.parameters() {
+        if name.starts_with("group.") {
+            assert_eq!(param.value.shape(), &[8, 2], "{} has the wrong shape", name);
+        }
+    }
+    assert_eq!(attention.predict(random((2, 3, 8), &mut rng)).shape(), &[2, 3, 8]);
+}
+
+#[test]
+#[should_panic(expected = "not divisible")]
+fn indivisible_dimensionality_is_rejected() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let _: MultiHeadedAttention = MultiHeadedAttention::new(3, 3, 3, 8, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+}
+
+#[test]
+fn scores_are_scaled_by_head_size() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let attention: SelfAttention = SelfAttention::new(3, 8, 2, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    let input = random((1, 3, 8), &mut rng);
+    let output = attention.predict(input.clone());
+
+    // Recompute softmax(QKᵀ/√d_k)V by hand from the block's own weights
+    let params: Vec<Array2<f32>> = attention.parameters().iter()
+        .map(|(_, param)| param.value.clone().into_dimensionality().unwrap())
+        .collect();
+    let words = input.index_axis(Axis(0), 0);
+    let (keys, queries, values) = (words.dot(&params[0]), words.dot(&params[1]), words.dot(&params[2]));
+    let mut weights = queries.dot(&keys.t()) / 2f32.sqrt();
+    for mut row in weights.rows_mut() {
+        let highest = row.fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+        row.mapv_inplace(|x| (x - highest).exp());
+        let total = row.sum();
+        row.mapv_inplace(|x| x / total);
+    }
+    let expected = weights.dot(&values);
+
+    for (a, b) in output.index_axis(Axis(0), 0).iter().zip(expected.iter()) {
+        assert!((a - b).abs() < 1e-5, "expected {} but found {}", b, a);
+    }
+}
+
+#[test]
+fn output_projection_is_shared_across_words() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let short: MultiHeadedAttention = MultiHeadedAttention::new(2, 2, 3, 8, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    let long: MultiHeadedAttention = MultiHeadedAttention::new(2, 2, 30, 8, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    let count = |attention: &MultiHeadedAttention| attention.parameters().iter().map(|(_, param)| param.value.len()).sum::<usize>();
+    assert_eq!(count(&short), count(&long));
+
+    for (name, param) in short.parameters() {
+        if name.starts_with("linear.") {
+            assert_eq!(param.value.shape()[0], 8, "{} doesn't project a single word", name);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,80 @@
+use ndarray::{Array2, ArrayD, Ix2};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::initializer::Initializer;
+
+fn variance(value: &ArrayD<f64>) -> f64 {
+    value.mapv(|x| x * x).mean().unwrap()
+}
+
+fn assert_orthonormal_rows(matrix: &Array2<f64>) {
+    let product = matrix.dot(&matrix.t());
+    for ((i, j), &x) in product.indexed_iter() {
+        let expected = if i == j { 1.0 } else { 0.0 };
+        assert!((x - expected).abs() < 1e-9, "product[{}, {}] is {}", i, j, x);
+    }
+}
+
+#[test]
+fn orthogonal_is_orthonormal() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for (rows, cols) in [(5, 5), (3, 8), (8, 3)] {
+        let matrix = Initializer::Orthogonal.initialize::<f64, _>(&[rows, cols], rows, cols, &mut rng)
+            .into_dimensionality::<Ix2>().unwrap();
+        // Whichever of the rows or columns are fewer must be orthonormal
+        if rows <= cols {
+            assert_orthonormal_rows(&matrix);
+        } else {
+            assert_orthonormal_rows(&matrix.t().to_owned());
+        }
+    }
+}
+
+#[test]
+fn truncated_normal_stays_within_two_standard_deviations() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let std = 0.02;
+    let value = Initializer::TruncatedNormal { std }.initialize::<f64, _>(&[200, 200], 200, 200, &mut rng);
+    let bound = 2.0 * std as f64;
+    assert!(value.iter().all(|x| x.abs() <= bound));
+    // Truncation at two standard deviations leaves about 77% of the variance
+    let expected = 0.774 * (std as f64).powi(2);
+    assert!((variance(&value) / expected - 1.0).abs() < 0.05, "variance {} isn't near {}", variance(&value), expected);
+}
+
+#[test]
+fn variance_scales_with_fan_in_and_fan_out() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (fan_in, fan_out) = (100.0, 300.0);
+    let cases = [
+        (Initializer::XavierUniform, 2.0 / (fan_in + fan_out)),
+        (Initializer::XavierNormal, 2.0 / (fan_in + fan_out)),
+        (Initializer::HeUniform, 2.0 / fan_in),
+        (Initializer::HeNormal, 2.0 / fan_in),
+        (Initializer::LeCunNormal, 1.0 / fan_in),
+    ];
+    for (initializer, expected) in cases {
+        let value = initializer.initialize::<f64, _>(&[100, 300], 100, 300, &mut rng);
+        let found = variance(&value);
+        assert!((found / expected - 1.0).abs() < 0.05, "{:?} has variance {} rather than {}", initializer, found, expected);
+    }
+}
+
+#[test]
+fn zeros_are_zero() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let value = Initializer::Zeros.initialize::<f32, _>(&[4, 3], 4, 3, &mut rng);
+    assert!(value.iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn truncated_normal_rejects_invalid_standard_deviations() {
+    for std in [-1.0, 0.0, f32::NAN, f32::INFINITY] {
+        assert!(Initializer::TruncatedNormal { std }.validate().is_err(), "{} was accepted", std);
+        let result = std::panic::catch_unwind(|| {
+            Initializer::TruncatedNormal { std }.initialize::<f64, _>(&[2, 2], 2, 2, &mut StdRng::seed_from_u64(0))
+        });
+        assert!(result.is_err(), "{} didn't panic", std);
+    }
+    assert!(Initializer::TruncatedNormal { std: 0.02 }.validate().is_ok());
+}
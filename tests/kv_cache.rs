@@ -0,0 +1,52 @@
+mod common;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+use common::{assert_same, decode, positions, random};
+
+const WORDS: usize = 7;
+const DIMENSIONALITY: usize = 8;
+
+#[test]
+fn cached_self_attention_matches_full_recomputation() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for positions in positions() {
+        let mut attention = SelfAttention::<f64>::new(WORDS, DIMENSIONALITY, 4, 1, true, positions, Initializer::XavierUniform, &mut rng);
+        for (_, param) in attention.parameters_mut() {
+            param.value.mapv_inplace(|x| x + rng.gen_range(-0.5..0.5));
+        }
+        let input = random((2, WORDS, DIMENSIONALITY), &mut rng);
+
+        let mut cache = attention.new_cache(2);
+        let cached = decode(&input, |word| attention.predict_step(word, &mut cache));
+        assert_eq!(cache.len(), WORDS);
+        assert_same(&cached, &attention.predict(input), positions);
+    }
+}
+
+#[test]
+fn cached_multi_headed_attention_matches_full_recomputation() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for positions in positions() {
+        let attention = MultiHeadedAttention::<f64>::new(2, 2, WORDS, DIMENSIONALITY, true, positions, Initializers::default(), &mut rng);
+        let input = random((3, WORDS, DIMENSIONALITY), &mut rng);
+
+        let mut cache = attention.new_cache(3);
+        let cached = decode(&input, |word| attention.predict_step(word, &mut cache));
+        assert_same(&cached, &attention.predict(input), positions);
+    }
+}
+
+#[test]
+#[should_panic(expected = "only a causal block")]
+fn bidirectional_attention_has_no_cache() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let attention = SelfAttention::<f64>::new(WORDS, DIMENSIONALITY, 4, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    let mut cache = attention.new_cache(1);
+    attention.predict_step(random((1, 1, DIMENSIONALITY), &mut rng), &mut cache);
+}
\ No newline at end of file
@@ -0,0 +1,102 @@
+mod common;
+
+use ndarray::{s, Array1, Array3, Axis};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::grad_check::check_gradients;
+use rusttransformer::initializer::Initializers;
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::PositionEncoding;
+use common::{assert_same, decode, positions, random, EPSILON, TOLERANCE};
+
+const WORDS: usize = 5;
+const DIMENSIONALITY: usize = 8;
+
+fn parameter_count(attention: &MultiHeadedAttention<f64>) -> usize {
+    attention.parameters().iter().map(|(_, param)| param.value.len()).sum()
+}
+
+#[test]
+fn fewer_groups_share_keys_and_values() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let standard = MultiHeadedAttention::<f64>::new(4, 4, WORDS, DIMENSIONALITY, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    let grouped = MultiHeadedAttention::<f64>::new(4, 2, WORDS, DIMENSIONALITY, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    let multi_query = MultiHeadedAttention::<f64>::new(4, 1, WORDS, DIMENSIONALITY, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+
+    // Every query head keeps its own queries, but each group has one key and value
+    for (name, param) in multi_query.parameters() {
+        match name.as_str() {
+            "group.0.key" | "group.0.value" => assert_eq!(param.value.shape(), &[8, 2]),
+            "group.0.query" => assert_eq!(param.value.shape(), &[8, 8]),
+            _ => assert!(name.starts_with("linear"), "unexpected parameter {}", name),
+        }
+    }
+    assert!(parameter_count(&multi_query) < parameter_count(&grouped));
+    assert!(parameter_count(&grouped) < parameter_count(&standard));
+
+    // Each query head still has its own attention map
+    let (output, weights) = grouped.predict_with_attention(random((2, WORDS, DIMENSIONALITY), &mut rng), None);
+    assert_eq!(output.shape(), &[2, WORDS, DIMENSIONALITY]);
+    assert_eq!(weights.shape(), &[2, 4, WORDS, WORDS]);
+}
+
+#[test]
+fn grouped_attention_gradients() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for groups in [1, 2] {
+        for positions in positions() {
+            let mut attention = MultiHeadedAttention::<f64>::new(4, groups, WORDS, DIMENSIONALITY, false, positions, Initializers::default(), &mut rng);
+            let input = random((2, WORDS, DIMENSIONALITY), &mut rng);
+            let errors = check_gradients(&mut attention, input, EPSILON, &mut rng);
+            assert!(!errors.is_empty());
+            for error in errors {
+                assert!(error.relative_error < TOLERANCE, "{} groups, {:?}: {} has relative error {}", groups, positions, error.name, error.relative_error);
+            }
+        }
+    }
+}
+
+#[test]
+fn cache_has_one_entry_per_group() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for positions in positions() {
+        let attention = MultiHeadedAttention::<f64>::new(4, 2, WORDS, DIMENSIONALITY, true, positions, Initializers::default(), &mut rng);
+        let input = random((2, WORDS, DIMENSIONALITY), &mut rng);
+
+        let mut cache = attention.new_cache(2);
+        assert_eq!(cache.len(), 2);
+        let cached = decode(&input, |word| attention.predict_step(word, &mut cache));
+        assert_same(&cached, &attention.predict(input), positions);
+    }
+}
+
+#[test]
+fn relative_biases_belong_to_each_query_head() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let positions = PositionEncoding::RelativeBias { buckets: 6, max_distance: 4 };
+    let mut attention = MultiHeadedAttention::<f64>::new(4, 1, WORDS, DIMENSIONALITY, false, positions, Initializers::default(), &mut rng);
+    let input: Array3<f64> = random((1, WORDS, DIMENSIONALITY), &mut rng);
+    let (_, before) = attention.predict_with_attention(input.clone(), None);
+
+    // Change the biases of the second query head only, which share one key and value
+    for (name, param) in attention.parameters_mut() {
+        if name == "group.0.relative_bias" {
+            assert_eq!(param.value.shape(), &[4*6]);
+            param.value.slice_mut(s![6..12]).assign(&Array1::linspace(-1.0, 1.0, 6));
+        }
+    }
+
+    let (_, after) = attention.predict_with_attention(input, None);
+    for head in 0..4 {
+        let changed = before.index_axis(Axis(1), head) != after.index_axis(Axis(1), head);
+        assert_eq!(changed, head == 1, "head {} changed: {}", head, changed);
+    }
+}
+
+#[test]
+#[should_panic(expected = "key/value groups")]
+fn indivisible_groups_are_rejected() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let _ = MultiHeadedAttention::<f64>::new(4, 3, WORDS, DIMENSIONALITY, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+}
\ No newline at end of file
@@ -0,0 +1,73 @@
+use ndarray::{arr1, ArrayD};
+use rusttransformer::optimizer::{Adam, AdamW, Momentum, Optimizer, Sgd};
+use rusttransformer::parameter::Parameter;
+
+fn parameter(value: &[f64], grad: &[f64]) -> Parameter<f64> {
+    let mut param = Parameter::new(arr1(value).into_dyn());
+    param.accumulate(&arr1(grad).into_dyn());
+    param
+}
+
+fn assert_close(found: &ArrayD<f64>, expected: &[f64]) {
+    for (f, e) in found.iter().zip(expected.iter()) {
+        assert!((f - e).abs() < 1e-6, "expected {:?} but found {}", expected, found);
+    }
+}
+
+/// Applies one optimisation step to a single parameter
+fn step<O: Optimizer<f64>>(optimizer: &mut O, param: &mut Parameter<f64>) {
+    optimizer.begin_step();
+    optimizer.update(0, param);
+}
+
+#[test]
+fn sgd_steps_against_the_gradient() {
+    let mut optimizer = Sgd::new(0.1);
+    let mut param = parameter(&[1.0, -2.0], &[0.5, 1.0]);
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[0.95, -2.1]);
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[0.9, -2.2]);
+}
+
+#[test]
+fn momentum_accumulates_velocity() {
+    let mut optimizer = Momentum::new(0.1, 0.9);
+    let mut param = parameter(&[1.0, -2.0], &[0.5, 1.0]);
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[0.95, -2.1]);
+
+    // The second velocity is 0.9·g + g
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[0.855, -2.29]);
+}
+
+#[test]
+fn adam_bias_correction_gives_full_first_steps() {
+    // At t = 1 the corrected moments are g and g², so every weight moves by the learning rate
+    let mut optimizer = Adam::new(0.1, 0.9, 0.999, 1e-8);
+    let mut param = parameter(&[1.0, -2.0], &[0.5, -4.0]);
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[0.9, -1.9]);
+
+    // Reversing the gradient: m̂ = (0.9·0.05 - 0.05) / 0.19 and v̂ = 0.25
+    param.zero_grad();
+    param.accumulate(&arr1(&[-0.5, -4.0]).into_dyn());
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[0.9 + 0.1 * (0.005 / 0.19) / 0.5, -1.8]);
+}
+
+#[test]
+fn adamw_decays_weights_independently_of_the_gradient() {
+    // With no gradient Adam doesn't move, so only the decay of lr·λ is left
+    let mut optimizer = AdamW::new(0.1, 0.9, 0.999, 1e-8, 0.01);
+    let mut param = parameter(&[2.0, -4.0], &[0.0, 0.0]);
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[1.998, -3.996]);
+
+    // The same decay applies however large the gradient is
+    let mut optimizer = AdamW::new(0.1, 0.9, 0.999, 1e-8, 0.01);
+    let mut param = parameter(&[2.0, -4.0], &[100.0, 0.001]);
+    step(&mut optimizer, &mut param);
+    assert_close(&param.value, &[1.898, -4.096]);
+}
\ No newline at end of file
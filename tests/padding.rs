@@ -0,0 +1,122 @@
+mod common;
+
+use ndarray::{arr1, arr2, s, Array2, Array3};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::dataset::PAD;
+use rusttransformer::encoder_block::EncoderBlock;
+use rusttransformer::grad_check::check_gradients;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::parameter::Parameter;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::self_attention::SelfAttention;
+use rusttransformer::transformer::{Transformer, TransformerConfig};
+use common::{assert_close, config, embedding, random, EPSILON, TOLERANCE};
+
+// The second review has its last two words padded
+fn padding() -> Array2<bool> {
+    arr2(&[[true, true, true, true], [true, true, false, false]])
+}
+
+// Changes the vectors of the padded words only
+fn repad(input: &Array3<f64>, rng: &mut StdRng) -> Array3<f64> {
+    let mut repadded = input.clone();
+    repadded.slice_mut(s![1, 2.., ..]).assign(&random((2, 5), rng));
+    repadded
+}
+
+// Wraps an encoder block so the gradient checker sees a fixed padding mask
+struct Padded(EncoderBlock<f64>);
+
+impl Block<f64> for Padded {
+    type Input = Array3<f64>;
+    type Output = Array3<f64>;
+
+    fn forward_propagate(&mut self, value: Self::Input) -> Self::Output {
+        self.0.forward_propagate_masked(value, Some(&padding()))
+    }
+
+    fn predict(&self, value: Self::Input) -> Self::Output {
+        self.0.predict_masked(value, Some(&padding()))
+    }
+
+    fn back_propagate(&mut self, error: Self::Output) -> Self::Input {
+        self.0.back_propagate(error)
+    }
+
+    fn parameters(&self) -> Vec<(String, &Parameter<f64>)> {
+        self.0.parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(String, &mut Parameter<f64>)> {
+        self.0.parameters_mut()
+    }
+}
+
+#[test]
+fn self_attention_ignores_padded_words() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let attention = SelfAttention::<f64>::new(4, 5, 5, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    let input = random((2, 4, 5), &mut rng);
+
+    let output = attention.predict_masked(input.clone(), Some(&padding()));
+    let repadded = attention.predict_masked(repad(&input, &mut rng), Some(&padding()));
+
+    assert_eq!(output, repadded);
+    assert!(output.slice(s![1, 2.., ..]).iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn padded_words_get_zero_gradient() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut attention = SelfAttention::<f64>::new(4, 5, 5, 1, false, PositionEncoding::Sinusoidal, Initializer::XavierUniform, &mut rng);
+    attention.forward_propagate_masked(random((2, 4, 5), &mut rng), Some(&padding()));
+
+    let input_grad = attention.back_propagate(random((2, 4, 5), &mut rng));
+
+    assert!(input_grad.slice(s![1, 2.., ..]).iter().all(|&x| x == 0.0));
+    assert!(input_grad.slice(s![1, ..2, ..]).iter().any(|&x| x != 0.0));
+}
+
+#[test]
+fn masked_encoder_block_gradients() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut encoder = Padded(EncoderBlock::new(4, 5, 1, 1, arr1(&[5, 8, 5]), PositionEncoding::Sinusoidal, Initializers::default(), &mut rng));
+    let input = random((2, 4, 5), &mut rng);
+
+    let output = encoder.predict(input.clone());
+    assert_eq!(output.slice(s![1, ..2, ..]), encoder.predict(repad(&input, &mut rng)).slice(s![1, ..2, ..]));
+
+    assert_close(check_gradients(&mut encoder, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn transformer_ignores_padding() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut embedding = embedding(&mut rng);
+    let config = TransformerConfig { num_words: 4, layer_sizes: vec![4, 8, 4], ..config() };
+    let mut first: Transformer = Transformer::new(config.clone(), embedding.clone(), &mut StdRng::seed_from_u64(4));
+    embedding.insert(PAD.to_string(), vec![5.0, -3.0, 2.0, 7.0]);
+    let mut second: Transformer = Transformer::new(config, embedding, &mut StdRng::seed_from_u64(4));
+
+    let reviews = arr2(&[["good", "film", PAD, PAD], ["bad", "bad", "film", PAD]]).mapv(|word| word.to_string());
+    assert_eq!(first.forward_propagate(reviews.clone()), second.forward_propagate(reviews.clone()));
+    assert_eq!(first.predict(reviews.clone()), second.predict(reviews));
+}
+
+#[test]
+fn zero_padding_embedding_stays_finite() {
+    // Without sinusoids, a zero padding vector reaches every layer norm with no variance
+    for positions in [PositionEncoding::Rotary, PositionEncoding::Alibi, PositionEncoding::RelativeBias { buckets: 4, max_distance: 4 }] {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut embedding = embedding(&mut rng);
+        embedding.insert(PAD.to_string(), vec![0.0; 4]);
+        let config = TransformerConfig { num_words: 4, layer_sizes: vec![4, 8, 4], positions, ..config() };
+        let mut transformer: Transformer = Transformer::new(config, embedding, &mut rng);
+
+        let reviews = arr2(&[["good", "film", PAD, PAD]]).mapv(|word| word.to_string());
+        assert!(transformer.predict(reviews.clone()).iter().all(|x| x.is_finite()), "{:?} predicts NaN", positions);
+        assert!(transformer.forward_propagate(reviews).iter().all(|x| x.is_finite()), "{:?} trains on NaN", positions);
+    }
+}
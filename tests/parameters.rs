@@ -0,0 +1,45 @@
+mod common;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::transformer::{Transformer, TransformerConfig};
+use common::{config, small_model};
+
+// Checkpoints and safetensors files store tensors under these names, so
+// renaming any of them breaks every saved model
+#[test]
+fn transformer_parameters_have_stable_names_and_shapes() {
+    let transformer: Transformer = small_model(TransformerConfig { num_encoders: 1, ..config() }, &mut StdRng::seed_from_u64(0));
+    let found: Vec<(String, Vec<usize>)> = transformer.parameters().into_iter()
+        .map(|(name, param)| (name, param.shape().to_vec()))
+        .collect();
+
+    // The layer norms have no trainable parameters, so only attention and dense layers appear
+    let expected = [
+        ("encoder.0.attn.group.0.key", vec![4, 2]),
+        ("encoder.0.attn.group.0.query", vec![4, 2]),
+        ("encoder.0.attn.group.0.value", vec![4, 2]),
+        ("encoder.0.attn.group.1.key", vec![4, 2]),
+        ("encoder.0.attn.group.1.query", vec![4, 2]),
+        ("encoder.0.attn.group.1.value", vec![4, 2]),
+        ("encoder.0.attn.linear.layer.0.weights", vec![4, 4]),
+        ("encoder.0.attn.linear.layer.0.biases", vec![4]),
+        ("encoder.0.feed_forward.layer.0.weights", vec![4, 8]),
+        ("encoder.0.feed_forward.layer.0.biases", vec![8]),
+        ("encoder.0.feed_forward.layer.1.weights", vec![8, 4]),
+        ("encoder.0.feed_forward.layer.1.biases", vec![4]),
+        ("classifier.layer.0.weights", vec![4, 1]),
+        ("classifier.layer.0.biases", vec![1]),
+    ];
+    let expected: Vec<(String, Vec<usize>)> = expected.into_iter().map(|(name, shape)| (name.to_string(), shape)).collect();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn mutable_parameters_match_parameters() {
+    let mut transformer: Transformer = small_model(config(), &mut StdRng::seed_from_u64(0));
+    let names: Vec<String> = transformer.parameters().into_iter().map(|(name, _)| name).collect();
+    let mutable: Vec<String> = transformer.parameters_mut().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, mutable);
+}
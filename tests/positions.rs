@@ -0,0 +1,149 @@
+mod common;
+
+use ndarray::{arr1, Array1, Array2, Axis};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rusttransformer::autograd::Tape;
+use rusttransformer::block::Block;
+use rusttransformer::dataset::PAD;
+use rusttransformer::grad_check::check_gradients;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::{alibi_bias, alibi_slopes, relative_buckets, rotate, PositionEncoding};
+use rusttransformer::self_attention::SelfAttention;
+use rusttransformer::transformer::{Transformer, TransformerConfig};
+use common::{assert_close, config, positions, random, small_model, EPSILON, TOLERANCE};
+
+#[test]
+fn rotary_scores_depend_on_relative_position() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (query, key): (Array1<f64>, Array1<f64>) = (random(6, &mut rng), random(6, &mut rng));
+
+    // Put the same query and key at every position, then rotate them
+    let mut tape = Tape::<f64>::new();
+    let queries = tape.leaf(query.broadcast((8, 6)).unwrap().to_owned().into_dyn());
+    let keys = tape.leaf(key.broadcast((8, 6)).unwrap().to_owned().into_dyn());
+    let queries = rotate(&mut tape, queries, 0);
+    let keys = rotate(&mut tape, keys, 0);
+    let queries: Array2<f64> = tape.value(queries).clone().into_dimensionality().unwrap();
+    let keys: Array2<f64> = tape.value(keys).clone().into_dimensionality().unwrap();
+
+    // Rotations keep lengths, and the score of positions (i, j) only depends on i - j
+    assert!((queries.row(5).dot(&queries.row(5)) - query.dot(&query)).abs() < 1e-12);
+    let scores = queries.dot(&keys.t());
+    for i in 1..8 {
+        for j in 1..8 {
+            assert!((scores[[i, j]] - scores[[i - 1, j - 1]]).abs() < 1e-12, "score ({}, {}) differs from ({}, {})", i, j, i - 1, j - 1);
+        }
+    }
+    assert!((scores[[0, 0]] - scores[[0, 1]]).abs() > 1e-6);
+}
+
+#[test]
+fn rotary_attention_gradients() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut attention = MultiHeadedAttention::<f64>::new(2, 2, 5, 8, false, PositionEncoding::Rotary, Initializers::default(), &mut rng);
+    let input = random((2, 5, 8), &mut rng);
+    assert_close(check_gradients(&mut attention, input, EPSILON, &mut rng), TOLERANCE);
+}
+
+#[test]
+fn rotary_attention_sees_word_order() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let attention = SelfAttention::<f64>::new(4, 4, 4, 1, false, PositionEncoding::Rotary, Initializer::XavierUniform, &mut rng);
+    let input = random((1, 4, 4), &mut rng);
+    let mut swapped = input.clone();
+    swapped.index_axis_mut(Axis(1), 1).assign(&input.index_axis(Axis(1), 2));
+    swapped.index_axis_mut(Axis(1), 2).assign(&input.index_axis(Axis(1), 1));
+
+    // Without positions, swapping two words would only swap their outputs
+    let output = attention.predict(input);
+    let swapped_output = attention.predict(swapped);
+    assert!((&output.index_axis(Axis(1), 0) - &swapped_output.index_axis(Axis(1), 0)).iter().any(|x| x.abs() > 1e-9));
+}
+
+#[test]
+#[should_panic(expected = "even head size")]
+fn rotary_needs_an_even_head_size() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let _ = SelfAttention::<f32>::new(4, 6, 3, 1, false, PositionEncoding::Rotary, Initializer::XavierUniform, &mut rng);
+}
+
+#[test]
+fn alibi_penalises_distance() {
+    assert_eq!(alibi_slopes(2), vec![0.0625, 0.00390625]);
+    let bias = alibi_bias::<f64>(0..4, 4, 0.5);
+    assert_eq!(bias[[1, 1]], 0.0);
+    assert_eq!(bias[[0, 3]], -1.5);
+    assert_eq!(bias[[3, 0]], -1.5);
+}
+
+#[test]
+fn relative_buckets_split_direction_and_grow_with_distance() {
+    let buckets = relative_buckets(0..40, 40, 8, 20, false);
+    assert_eq!(buckets[[5, 5]], 0);
+    assert_eq!(buckets[[5, 4]], 1);
+    assert_eq!(buckets[[4, 5]], 5);
+    assert!(buckets.iter().all(|&bucket| bucket < 8));
+
+    // Distances past `max_distance` share the last bucket of their direction
+    assert_eq!(buckets[[39, 0]], 3);
+    assert_eq!(buckets[[0, 39]], 7);
+    for j in 1..39 {
+        assert!(buckets[[39, j - 1]] >= buckets[[39, j]]);
+    }
+}
+
+#[test]
+fn relative_attention_gradients() {
+    let mut rng = StdRng::seed_from_u64(4);
+    for positions in [PositionEncoding::Alibi, PositionEncoding::RelativeBias { buckets: 4, max_distance: 8 }] {
+        let mut attention = MultiHeadedAttention::<f64>::new(2, 2, 5, 8, false, positions, Initializers::default(), &mut rng);
+
+        // Move the learned biases away from zero so every bucket matters
+        for (_, param) in attention.parameters_mut() {
+            param.value.mapv_inplace(|x| x + rng.gen_range(-0.5..0.5));
+        }
+
+        let input = random((2, 5, 8), &mut rng);
+        for error in check_gradients(&mut attention, input, EPSILON, &mut rng) {
+            assert!(error.relative_error < TOLERANCE, "{:?} {} has relative error {}", positions, error.name, error.relative_error);
+        }
+    }
+}
+
+#[test]
+fn relative_attention_runs_on_longer_sequences() {
+    let mut rng = StdRng::seed_from_u64(5);
+    for positions in [PositionEncoding::Alibi, PositionEncoding::RelativeBias { buckets: 8, max_distance: 16 }, PositionEncoding::Rotary] {
+        let mut attention = MultiHeadedAttention::<f64>::new(2, 2, 12, 8, false, positions, Initializers::default(), &mut rng);
+        let input = random((2, 30, 8), &mut rng);
+        assert_eq!(attention.predict(input.clone()), attention.forward_propagate(input.clone()));
+        assert_eq!(attention.back_propagate(random((2, 30, 8), &mut rng)).shape(), &[2, 30, 8]);
+    }
+}
+
+#[test]
+fn transformer_scores_reviews_longer_than_it_was_built_for() {
+    let mut rng = StdRng::seed_from_u64(6);
+    let review = |words: &[&str]| Array2::from_shape_fn((1, words.len()), |(_, i)| words[i].to_string());
+
+    for positions in positions() {
+        let mut transformer: Transformer<f64> = small_model(TransformerConfig { positions, ..config() }, &mut rng);
+
+        // A long review can be scored and trained on, and padding it changes nothing
+        let long = review(&["good", "film", "bad", "film", "good", "good", "bad"]);
+        let padded = review(&["good", "film", "bad", "film", "good", "good", "bad", PAD, PAD]);
+        let prediction = transformer.predict(long.clone());
+        assert!((prediction[0] - transformer.predict(padded)[0]).abs() < 1e-12, "{:?} depends on padding", positions);
+        assert_eq!(transformer.forward_propagate(long), prediction);
+        transformer.back_propagate(arr1(&[1.0]));
+    }
+}
+
+#[test]
+fn configs_without_positions_are_sinusoidal() {
+    let json = r#"{"num_words": 3, "dimensionality": 4, "num_encoders": 1, "num_heads": 2, "layer_sizes": [4, 4]}"#;
+    let config: TransformerConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.positions, PositionEncoding::Sinusoidal);
+}
\ No newline at end of file
@@ -0,0 +1,36 @@
+mod common;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::sync::Arc;
+use std::thread;
+use rusttransformer::block::Block;
+use rusttransformer::transformer::Transformer;
+use common::{config, reviews, small_model};
+
+fn transformer(seed: u64) -> Transformer {
+    small_model(config(), &mut StdRng::seed_from_u64(seed))
+}
+
+#[test]
+fn predict_matches_forward_propagate() {
+    let mut transformer = transformer(0);
+    let predicted = transformer.predict(reviews(3, 0));
+    let propagated = transformer.forward_propagate(reviews(3, 0));
+    assert_eq!(predicted, propagated);
+}
+
+#[test]
+fn predict_is_shareable_between_threads() {
+    let mut reference = transformer(1);
+    let shared = Arc::new(transformer(1));
+
+    let handles: Vec<_> = (0..4).map(|offset| {
+        let transformer = Arc::clone(&shared);
+        thread::spawn(move || transformer.predict(reviews(3, offset)))
+    }).collect();
+
+    for (offset, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join().unwrap(), reference.forward_propagate(reviews(3, offset)));
+    }
+}
\ No newline at end of file
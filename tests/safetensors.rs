@@ -0,0 +1,113 @@
+mod common;
+
+use ndarray::{Array2, ArrayD};
+use std::collections::HashMap;
+use std::fs;
+use rusttransformer::block::Block;
+use rusttransformer::checkpoint::{CheckpointError, FORMAT_VERSION};
+use rusttransformer::float::Float;
+use rusttransformer::initializer::{Initializer, Initializers};
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::safetensors::{read_safetensors, write_safetensors};
+use rusttransformer::transformer::{Transformer, TransformerConfig};
+use common::{config, seeded_transformer, temp_path};
+
+fn round_trip<F: Float>(name: &str) {
+    let path = temp_path(name, "safetensors");
+    let exported: Transformer<F> = seeded_transformer(config(), 1);
+    exported.export_safetensors(&path).unwrap();
+    let mut imported: Transformer<F> = seeded_transformer(config(), 2);
+    imported.import_safetensors(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    for ((name, original), (_, copy)) in exported.parameters().iter().zip(imported.parameters().iter()) {
+        assert_eq!(original.value, copy.value, "{} changed", name);
+    }
+    let reviews = Array2::from_shape_fn((1, 3), |(_, i)| ["good", "bad", "film"][i].to_string());
+    assert_eq!(exported.predict(reviews.clone()), imported.predict(reviews));
+}
+
+#[test]
+fn f32_weights_round_trip() {
+    round_trip::<f32>("f32");
+}
+
+#[test]
+fn f64_weights_round_trip() {
+    round_trip::<f64>("f64");
+}
+
+/// Writes a file with the given header size and JSON header, then tries to read it
+fn read_raw(name: &str, header_size: u64, header: &str) -> Result<(), CheckpointError> {
+    let path = temp_path(name, "safetensors");
+    let mut bytes = header_size.to_le_bytes().to_vec();
+    bytes.extend(header.as_bytes());
+    fs::write(&path, bytes).unwrap();
+    let result = read_safetensors::<f32>(&path).map(|_| ());
+    fs::remove_file(&path).unwrap();
+    result
+}
+
+#[test]
+fn overflowing_header_size_is_rejected() {
+    assert!(matches!(read_raw("header_size", u64::MAX, "{}"), Err(CheckpointError::Invalid(_))));
+}
+
+#[test]
+fn overflowing_tensor_size_is_rejected() {
+    let header = format!(r#"{{"x":{{"dtype":"F32","shape":[{},{}],"data_offsets":[0,0]}}}}"#, usize::MAX, 2);
+    assert!(matches!(read_raw("tensor_size", header.len() as u64, &header), Err(CheckpointError::Invalid(_))));
+}
+
+/// Exports a fresh transformer, lets `edit` change the metadata, then imports it into an identical one
+fn import_edited(name: &str, edit: impl FnOnce(&mut HashMap<String, String>)) -> Result<(), CheckpointError> {
+    let path = temp_path(name, "safetensors");
+    let exported: Transformer = seeded_transformer(config(), 1);
+    exported.export_safetensors(&path).unwrap();
+    let (tensors, mut metadata) = read_safetensors::<f32>(&path).unwrap();
+    edit(&mut metadata);
+    let tensors: Vec<(String, &ArrayD<f32>)> = tensors.iter().map(|(name, tensor)| (name.clone(), tensor)).collect();
+    write_safetensors(&path, &tensors, &metadata).unwrap();
+
+    let mut imported: Transformer = seeded_transformer(config(), 2);
+    let result = imported.import_safetensors(&path);
+    fs::remove_file(&path).unwrap();
+    result
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let result = import_edited("version", |metadata| { metadata.insert("format_version".to_string(), (FORMAT_VERSION + 1).to_string()); });
+    assert!(matches!(result, Err(CheckpointError::Version { found, expected }) if found == FORMAT_VERSION + 1 && expected == FORMAT_VERSION));
+}
+
+#[test]
+fn missing_metadata_is_rejected() {
+    for key in ["format_version", "config"] {
+        let result = import_edited("missing_metadata", |metadata| { metadata.remove(key); });
+        assert!(matches!(result, Err(CheckpointError::Invalid(reason)) if reason.contains(key)), "missing {} was accepted", key);
+    }
+}
+
+#[test]
+fn other_configs_are_rejected() {
+    // The tensors still fit, but the positions they were trained with differ
+    let other = TransformerConfig { positions: PositionEncoding::Rotary, ..config() };
+    let result = import_edited("config", |metadata| { metadata.insert("config".to_string(), serde_json::to_string(&other).unwrap()); });
+    assert!(matches!(result, Err(CheckpointError::Invalid(_))));
+}
+
+#[test]
+fn configs_differing_only_in_initialization_are_accepted() {
+    let path = temp_path("initializers", "safetensors");
+    let exported: Transformer = seeded_transformer(config(), 1);
+    exported.export_safetensors(&path).unwrap();
+    let initializers = Initializers { attention: Initializer::Orthogonal, weights: Initializer::TruncatedNormal { std: 0.02 }, ..Initializers::default() };
+    let other = TransformerConfig { initializers, ..config() };
+    let mut imported: Transformer = seeded_transformer(other, 2);
+    imported.import_safetensors(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let reviews = Array2::from_shape_fn((1, 3), |(_, i)| ["good", "bad", "film"][i].to_string());
+    assert_eq!(exported.predict(reviews.clone()), imported.predict(reviews));
+}
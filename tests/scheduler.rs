@@ -0,0 +1,56 @@
+use rusttransformer::scheduler::{Constant, CosineDecay, InverseSqrt, LrScheduler, StepDecay, Warmup};
+
+fn assert_close(found: f32, expected: f32) {
+    assert!((found - expected).abs() < 1e-6, "expected {} but found {}", expected, found);
+}
+
+#[test]
+fn warmup_ramps_linearly_to_the_schedule() {
+    let schedule = Warmup::new(Constant::new(0.2), 10);
+    assert_close(schedule.learning_rate(1), 0.02);
+    assert_close(schedule.learning_rate(5), 0.1);
+    assert_close(schedule.learning_rate(10), 0.2);
+    assert_close(schedule.learning_rate(100), 0.2);
+}
+
+#[test]
+fn cosine_decay_runs_from_base_to_minimum() {
+    let schedule = CosineDecay::new(1.0, 0.1, 100);
+    assert_close(schedule.learning_rate(1), 1.0);
+    assert_close(schedule.learning_rate(51), 0.55);
+    assert_close(schedule.learning_rate(101), 0.1);
+    assert_close(schedule.learning_rate(1000), 0.1);
+}
+
+#[test]
+fn step_decay_changes_at_each_boundary() {
+    let schedule = StepDecay::new(1.0, 0.5, 10);
+    // Steps count from one, so ten steps run at each rate
+    assert_close(schedule.learning_rate(1), 1.0);
+    assert_close(schedule.learning_rate(10), 1.0);
+    assert_close(schedule.learning_rate(11), 0.5);
+    assert_close(schedule.learning_rate(20), 0.5);
+    assert_close(schedule.learning_rate(21), 0.25);
+}
+
+#[test]
+fn inverse_sqrt_peaks_at_the_end_of_warmup() {
+    let schedule = InverseSqrt::new(64, 400);
+    let peak = schedule.learning_rate(400);
+    assert_close(peak, 1.0 / (8.0 * 20.0));
+    assert!(schedule.learning_rate(399) < peak);
+    assert!(schedule.learning_rate(401) < peak);
+    assert_close(schedule.learning_rate(1600), peak / 2.0);
+}
+
+#[test]
+#[should_panic(expected = "at least one step")]
+fn cosine_decay_needs_steps() {
+    CosineDecay::new(1.0, 0.1, 0);
+}
+
+#[test]
+#[should_panic(expected = "at least one step")]
+fn step_decay_needs_steps() {
+    StepDecay::new(1.0, 0.5, 0);
+}
\ No newline at end of file
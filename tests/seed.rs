@@ -0,0 +1,34 @@
+mod common;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::transformer::Transformer;
+use common::{config, small_model};
+
+fn transformer(seed: u64) -> Transformer {
+    small_model(config(), &mut StdRng::seed_from_u64(seed))
+}
+
+#[test]
+fn same_seed_builds_identical_parameters() {
+    let (first, second) = (transformer(7), transformer(7));
+    let (first, second) = (first.parameters(), second.parameters());
+
+    assert_eq!(first.len(), second.len());
+    for ((name, a), (other, b)) in first.iter().zip(second.iter()) {
+        assert_eq!(name, other);
+        // Compare bit patterns so that even a difference in rounding is caught
+        let a: Vec<u32> = a.value.iter().map(|x| x.to_bits()).collect();
+        let b: Vec<u32> = b.value.iter().map(|x| x.to_bits()).collect();
+        assert_eq!(a, b, "{} differs between builds", name);
+    }
+}
+
+#[test]
+fn different_seeds_build_different_parameters() {
+    let (first, second) = (transformer(7), transformer(8));
+    let differs = first.parameters().iter().zip(second.parameters().iter())
+        .any(|((_, a), (_, b))| a.value != b.value);
+    assert!(differs);
+}
\ No newline at end of file
@@ -0,0 +1,92 @@
+mod common;
+
+use ndarray::Array2;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rusttransformer::block::Block;
+use rusttransformer::grad_check::check_gradients;
+use rusttransformer::initializer::Initializers;
+use rusttransformer::multi_headed_attention::MultiHeadedAttention;
+use rusttransformer::positional_encoder::PositionEncoding;
+use rusttransformer::window::Window;
+use common::{assert_same, decode, positions, random, EPSILON, TOLERANCE};
+
+const WORDS: usize = 7;
+const DIMENSIONALITY: usize = 8;
+
+#[test]
+fn pattern_grows_with_words_times_window() {
+    let window = Window::new(4, vec![0, 1]);
+    let pattern = window.pattern(0..512, 512, false);
+
+    // Every word sees at most 2w + 1 neighbours and the global words, which see everything
+    assert!(pattern.len() <= 510 * (2 * 4 + 1 + 2) + 2 * 512);
+    assert_eq!(pattern.row(0).len(), 512);
+    assert_eq!(pattern.pairs().filter(|&(i, _)| i == 100).map(|(_, j)| j).collect::<Vec<_>>(), vec![0, 1, 96, 97, 98, 99, 100, 101, 102, 103, 104]);
+
+    // A causal pattern never pairs a word with a later one
+    let causal = window.pattern(0..512, 512, true);
+    assert!(causal.pairs().all(|(i, j)| j <= i));
+    assert_eq!(causal.row(0).len(), 1);
+}
+
+#[test]
+fn wide_window_matches_full_attention() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let padding = Array2::from_shape_fn((2, WORDS), |(b, i)| i < WORDS - 2 * b);
+    for causal in [false, true] {
+        for positions in positions() {
+            let full = MultiHeadedAttention::<f64>::new(2, 2, WORDS, DIMENSIONALITY, causal, positions, Initializers::default(), &mut StdRng::seed_from_u64(1));
+            let mut windowed = MultiHeadedAttention::<f64>::new(2, 2, WORDS, DIMENSIONALITY, causal, positions, Initializers::default(), &mut StdRng::seed_from_u64(1));
+            windowed.set_window(Some(Window::new(WORDS, vec![])));
+            let input = random((2, WORDS, DIMENSIONALITY), &mut rng);
+
+            assert_same(&windowed.predict(input.clone()), &full.predict(input.clone()), positions);
+            assert_same(&windowed.predict_masked(input.clone(), Some(&padding)), &full.predict_masked(input, Some(&padding)), positions);
+        }
+    }
+}
+
+#[test]
+fn words_only_attend_within_the_window() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut attention = MultiHeadedAttention::<f64>::new(2, 1, WORDS, DIMENSIONALITY, false, PositionEncoding::Sinusoidal, Initializers::default(), &mut rng);
+    attention.set_window(Some(Window::new(1, vec![3])));
+    let (_, weights) = attention.predict_with_attention(random((1, WORDS, DIMENSIONALITY), &mut rng), None);
+
+    for ((_, _, i, j), &weight) in weights.indexed_iter() {
+        let visible = i.abs_diff(j) <= 1 || i == 3 || j == 3;
+        assert_eq!(weight > 0.0, visible, "word {} gives word {} a weight of {}", i, j, weight);
+    }
+}
+
+#[test]
+fn windowed_attention_gradients() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for causal in [false, true] {
+        for positions in positions() {
+            let mut attention = MultiHeadedAttention::<f64>::new(4, 2, WORDS, DIMENSIONALITY, causal, positions, Initializers::default(), &mut rng);
+            attention.set_window(Some(Window::new(1, vec![2])));
+            let input = random((2, WORDS, DIMENSIONALITY), &mut rng);
+            let errors = check_gradients(&mut attention, input, EPSILON, &mut rng);
+            assert!(!errors.is_empty());
+            for error in errors {
+                assert!(error.relative_error < TOLERANCE, "causal {}, {:?}: {} has relative error {}", causal, positions, error.name, error.relative_error);
+            }
+        }
+    }
+}
+
+#[test]
+fn cached_windowed_attention_matches_full_recomputation() {
+    let mut rng = StdRng::seed_from_u64(4);
+    for positions in positions() {
+        let mut attention = MultiHeadedAttention::<f64>::new(2, 2, WORDS, DIMENSIONALITY, true, positions, Initializers::default(), &mut rng);
+        attention.set_window(Some(Window::new(2, vec![1])));
+        let input = random((2, WORDS, DIMENSIONALITY), &mut rng);
+
+        let mut cache = attention.new_cache(2);
+        let cached = decode(&input, |word| attention.predict_step(word, &mut cache));
+        assert_same(&cached, &attention.predict(input), positions);
+    }
+}
\ No newline at end of file